@@ -0,0 +1,74 @@
+use clap::{ArgAction, Args};
+use libpicacg::responses::ComicMetadata;
+use serde::{Deserialize, Serialize};
+
+use crate::{filter::Filterable, parse::parse_comic_id};
+
+/// Tags, authors, uploaders and comic ids that are never listed or downloaded.
+#[derive(Args, Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Blocklist {
+    #[clap(long = "tag", action = ArgAction::Append)]
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[clap(long = "author", action = ArgAction::Append)]
+    #[serde(default)]
+    pub authors: Vec<String>,
+    /// Matched against `Creator.name`.
+    #[clap(long = "uploader", action = ArgAction::Append)]
+    #[serde(default)]
+    pub uploaders: Vec<String>,
+    #[clap(long = "cid", action = ArgAction::Append, value_parser = parse_comic_id)]
+    #[serde(default)]
+    pub cids: Vec<String>,
+}
+
+impl Blocklist {
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+            && self.authors.is_empty()
+            && self.uploaders.is_empty()
+            && self.cids.is_empty()
+    }
+
+    /// Whether deciding on a comic needs more than its id.
+    pub fn needs_metadata(&self) -> bool {
+        !(self.tags.is_empty() && self.authors.is_empty() && self.uploaders.is_empty())
+    }
+
+    pub fn blocks_cid(&self, cid: &str) -> bool {
+        self.cids.iter().any(|c| c == cid)
+    }
+
+    pub fn blocks<T: Filterable>(&self, value: &T) -> bool {
+        if self.blocks_cid(value.id()) || self.authors.iter().any(|a| a == value.author()) {
+            return true;
+        }
+        let tags = value.tags();
+        self.tags.iter().any(|t| tags.contains(&t.as_str()))
+    }
+
+    pub fn blocks_metadata(&self, value: &ComicMetadata) -> bool {
+        self.blocks(&value.metadata) || self.uploaders.contains(&value.creator.name)
+    }
+
+    pub fn add(&mut self, other: &Blocklist) {
+        fn merge(into: &mut Vec<String>, from: &[String]) {
+            for value in from {
+                if !into.contains(value) {
+                    into.push(value.clone());
+                }
+            }
+        }
+        merge(&mut self.tags, &other.tags);
+        merge(&mut self.authors, &other.authors);
+        merge(&mut self.uploaders, &other.uploaders);
+        merge(&mut self.cids, &other.cids);
+    }
+
+    pub fn remove(&mut self, other: &Blocklist) {
+        self.tags.retain(|v| !other.tags.contains(v));
+        self.authors.retain(|v| !other.authors.contains(v));
+        self.uploaders.retain(|v| !other.uploaders.contains(v));
+        self.cids.retain(|v| !other.cids.contains(v));
+    }
+}
@@ -1,21 +1,31 @@
 #![allow(unused)]
 
 use std::{
-    io::{stdout, Write},
-    ops::{Deref, DerefMut},
+    io::{self, stdout, Write},
+    marker::PhantomData,
+    net::SocketAddr,
+    ops::Deref,
     path::PathBuf,
     str::FromStr,
-    sync::Arc,
-    time::Duration,
+    sync::{Arc, Mutex, RwLock as StdRwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use libpicacg::{error::Error, Api, Pagible};
-use reqwest::{ClientBuilder, Proxy, RequestBuilder, redirect::Policy, header::HeaderName};
+use libpicacg::{
+    error::Error,
+    responses::{ComicMetadata, PictureDownloadResounce},
+    Api, Pagible,
+};
+use bytes::Bytes;
+use futures::{future::BoxFuture, FutureExt, StreamExt};
+use reqwest::{Proxy, RequestBuilder, Response, Url, redirect::Policy, header::HeaderName};
 use size_utils::Size;
 use tokio::{
     fs,
     io::{AsyncReadExt, AsyncWriteExt},
-    sync::RwLock, time::Instant,
+    sync::{RwLock, Semaphore},
+    task::JoinSet,
+    time::Instant,
 };
 
 pub fn to_full_width_char(c: char) -> char {
@@ -31,36 +41,617 @@ pub fn path_escape(path: &str) -> String {
     path
 }
 
-use crate::console::Console;
+use crate::{
+    blocklist::Blocklist,
+    command::ImageQuality,
+    config::{DownloadTimeouts, RetryPolicy, Session, Templates, Timeouts},
+    console::Console,
+    dns::DohResolver,
+    pager::{self, PageRange},
+    progress::{ConsoleProgress, Progress, ProgressSink},
+    rate::{Bandwidth, RateLimiter},
+    selector::EpSelector,
+};
 
+/// A logged in client. Clones share the session, the connection pools and
+/// the concurrency and rate limits, so one login serves any number of tasks.
+#[derive(Clone)]
 pub struct Client {
-    api: Api,
+    /// Replaced as a whole when logging in, so calls in flight keep the `Api`
+    /// they started with.
+    api: Arc<StdRwLock<Arc<Api>>>,
+    /// Handed to every new `Api`, so logins keep the proxy and timeout.
+    api_client: reqwest::Client,
+    image_quality: ImageQuality,
     client: reqwest::Client,
+    game_client: reqwest::Client,
+    timeouts: DownloadTimeouts,
+    image_server: Option<String>,
+    blocklist: Arc<Blocklist>,
+    templates: Arc<Templates>,
+    semaphore: Option<Arc<Semaphore>>,
+    credentials: Arc<Mutex<Option<(String, String)>>>,
+    password_prompt: Option<fn(&str) -> Option<String>>,
+    session: Arc<Mutex<Option<Session>>>,
+    /// Held while the session is refreshed, so one expired token leads to one login.
+    refresh: Arc<tokio::sync::Mutex<()>>,
+    limiter: Option<Arc<RateLimiter>>,
+    bandwidth: Option<Arc<Bandwidth>>,
+    retry: RetryPolicy,
+    progress: Arc<dyn ProgressSink>,
+}
+
+/// Whether `error` means the session token was rejected and a new login is needed.
+pub fn is_auth_error(error: &Error) -> bool {
+    matches!(error, Error::Api { code, .. } if *code == 401)
+}
+
+/// What to do about a failed call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retry {
+    /// The session token expired, log in again and repeat the call.
+    Relogin,
+    /// A network error or a server error, worth another try after a delay.
+    Again,
+    /// The request itself is wrong, repeating it will not help.
+    Fail,
+}
+
+pub fn classify(error: &Error) -> Retry {
+    match error {
+        _ if is_auth_error(error) => Retry::Relogin,
+        Error::Api { code, .. } if *code >= 500 => Retry::Again,
+        Error::Api { .. } => Retry::Fail,
+        Error::Request(err) => match err.status() {
+            Some(status) if status.is_server_error() => Retry::Again,
+            Some(_) => Retry::Fail,
+            None if err.is_timeout() || err.is_connect() || err.is_request() || err.is_body() => {
+                Retry::Again
+            }
+            None => Retry::Fail,
+        },
+        Error::Io(err) if err.kind() == io::ErrorKind::TimedOut => Retry::Again,
+        _ => Retry::Fail,
+    }
+}
+
+/// Awaits `$client.$method(..)` under the client's [`RetryPolicy`]: network
+/// and server errors are retried with backoff, other errors are returned.
+/// `api.$method(..)` calls the `Api` through [`Client::call`], which takes care
+/// of the rate limit and of expired sessions.
+#[macro_export]
+macro_rules! retry {
+    (@run $client:ident, $call:expr) => {{
+        let mut attempt = 0;
+        loop {
+            let result = $call.await;
+            let err = match result {
+                Ok(_) => break result,
+                Err(ref err) => err,
+            };
+            match $crate::client::classify(err) {
+                $crate::client::Retry::Again => match $client.retry_delay(attempt) {
+                    Some(delay) => {
+                        $crate::console::Console::print_error(err);
+                        attempt += 1;
+                        tokio::time::sleep(delay).await;
+                    }
+                    None => break result,
+                },
+                _ => break result,
+            }
+        }
+    }};
+    ($client:ident, api.$method:ident($($arg:expr),* $(,)?)) => {
+        $crate::retry!(@run $client, $client.call(|api| Box::pin(api.$method($($arg),*))))
+    };
+    ($client:ident, $method:ident($($arg:expr),* $(,)?)) => {
+        $crate::retry!(@run $client, $client.$method($($arg),*))
+    };
 }
 
-impl Deref for Client {
+/// Waits for the next chunk of `response`. Returns `None` when nothing arrived
+/// within `read_timeout` or the body ended early, so the download is restarted.
+async fn read_chunk(
+    response: &mut Response,
+    read_timeout: Option<Duration>,
+) -> Option<reqwest::Result<Option<Bytes>>> {
+    let chunk = match read_timeout {
+        Some(read_timeout) => match tokio::time::timeout(read_timeout, response.chunk()).await {
+            Ok(chunk) => chunk,
+            Err(_) => {
+                Console::clear_line();
+                eprintln!("No data received for {}s, restarting", read_timeout.as_secs());
+                return None;
+            }
+        },
+        None => response.chunk().await,
+    };
+    match chunk {
+        Ok(None) => None,
+        chunk => Some(chunk),
+    }
+}
+
+/// The `Api` of the current session, lent to the closure of [`Client::call`]
+/// for one call.
+pub struct ApiCall<'env> {
+    api: Arc<Api>,
+    /// Lets the futures of a call borrow from the caller for as long as `'env`.
+    _env: PhantomData<&'env ()>,
+}
+
+impl Deref for ApiCall<'_> {
     type Target = Api;
     fn deref(&self) -> &Self::Target {
         &self.api
     }
 }
 
-impl DerefMut for Client {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.api
+/// Asks for the size of a download with a HEAD request. Each request is cut
+/// off after `timeout`, failed ones are repeated under `retry`.
+async fn content_length(
+    request: &RequestBuilder,
+    timeout: Option<Duration>,
+    retry: RetryPolicy,
+) -> Result<u64, Error> {
+    let mut attempt = 0;
+    loop {
+        let send = request.try_clone().unwrap().send();
+        let response = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, send).await.ok(),
+            None => Some(send.await),
+        };
+        let err = match response.map(|response| response.and_then(Response::error_for_status)) {
+            Some(Ok(response)) => {
+                return response
+                    .headers()
+                    .get(HeaderName::from_static("content-length"))
+                    .and_then(|length| length.to_str().ok()?.parse().ok())
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, "no content-length in the response")
+                            .into()
+                    });
+            }
+            Some(Err(err)) => {
+                let err = Error::from(err);
+                if classify(&err) != Retry::Again {
+                    return Err(err);
+                }
+                err
+            }
+            None => io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("no answer to a HEAD request within {}s", timeout.unwrap().as_secs()),
+            )
+            .into(),
+        };
+        let Some(delay) = retry.delay(attempt) else {
+            return Err(err);
+        };
+        Console::print_error(&err);
+        attempt += 1;
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Sets up a [`Client`]. Every setting goes in here, so the `Api` and the
+/// HTTP clients used for downloads are built once and keep their connections.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), libpicacg::error::Error> {
+/// use std::time::Duration;
+/// use picacg::client::ClientBuilder;
+///
+/// let client = ClientBuilder::new()
+///     .proxy(Some(reqwest::Proxy::all("socks5h://localhost:1080")?))
+///     .timeout(Some(Duration::from_secs(10)))
+///     .concurrency(Some(8))
+///     .rate_limit(Some(2.0), None)
+///     .build()?;
+/// client.login("user", "password").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ClientBuilder {
+    api_proxy: Option<Proxy>,
+    cdn_proxy: Option<Proxy>,
+    timeouts: DownloadTimeouts,
+    user_agent: Option<String>,
+    concurrency: Option<usize>,
+    rate_limit: Option<f64>,
+    rate_jitter: Option<Duration>,
+    bandwidth: Option<u64>,
+    retry: RetryPolicy,
+    image_quality: ImageQuality,
+    image_server: Option<String>,
+    resolve: Vec<(String, Vec<SocketAddr>)>,
+    doh: Option<Url>,
+    /// Built from `doh` by [`ClientBuilder::build`].
+    dns_resolver: Option<Arc<DohResolver>>,
+    progress: Arc<dyn ProgressSink>,
+    templates: Templates,
+    blocklist: Blocklist,
+    password_prompt: Option<fn(&str) -> Option<String>>,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self {
+            api_proxy: None,
+            cdn_proxy: None,
+            timeouts: DownloadTimeouts::default(),
+            user_agent: None,
+            concurrency: None,
+            rate_limit: None,
+            rate_jitter: None,
+            bandwidth: None,
+            retry: RetryPolicy::default(),
+            image_quality: ImageQuality::default(),
+            image_server: None,
+            resolve: Vec::new(),
+            doh: None,
+            dns_resolver: None,
+            progress: Arc::new(ConsoleProgress),
+            templates: Templates::default(),
+            blocklist: Blocklist::default(),
+            password_prompt: None,
+        }
+    }
+}
+
+impl ClientBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses `proxy` for API calls and downloads alike, `None` connects
+    /// directly even when `HTTP_PROXY` and the like are set.
+    pub fn proxy(self, proxy: Option<Proxy>) -> Self {
+        self.api_proxy(proxy.clone()).cdn_proxy(proxy)
+    }
+
+    pub fn api_proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.api_proxy = proxy;
+        self
+    }
+
+    /// Sets the proxy for image and game downloads.
+    pub fn cdn_proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.cdn_proxy = proxy;
+        self
+    }
+
+    /// Sets the timeout of API calls, from connecting to the last byte of the
+    /// answer. This is `api.total` of [`ClientBuilder::download_timeouts`], the
+    /// one set last wins.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeouts.api.total = timeout.map(|timeout| timeout.as_secs());
+        self
+    }
+
+    /// Timeouts of API calls, image downloads and game downloads.
+    pub fn download_timeouts(mut self, timeouts: DownloadTimeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// User agent of image and game downloads, API calls keep the one the
+    /// `Api` sends.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Limits how many images are downloaded at the same time, `None` for no limit.
+    pub fn concurrency(mut self, concurrency: Option<usize>) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Limits API calls to `per_second` a second, `None` for no limit.
+    pub fn rate_limit(mut self, per_second: Option<f64>, jitter: Option<Duration>) -> Self {
+        self.rate_limit = per_second;
+        self.rate_jitter = jitter;
+        self
+    }
+
+    /// Limits the bytes all downloads receive together to `per_second` a
+    /// second, `None` for no limit.
+    pub fn bandwidth(mut self, per_second: Option<u64>) -> Self {
+        self.bandwidth = per_second;
+        self
+    }
+
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn image_quality(mut self, quality: ImageQuality) -> Self {
+        self.image_quality = quality;
+        self
+    }
+
+    /// Downloads images from `server` instead of the server the API picked.
+    pub fn image_server(mut self, server: Option<String>) -> Self {
+        self.image_server = server.map(|server| server.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Pins hosts to addresses and looks the others up at the DNS-over-HTTPS
+    /// endpoint `doh` when given, for API calls and downloads alike. The
+    /// endpoint is asked through the API proxy.
+    pub fn dns(
+        mut self,
+        resolve: Vec<(String, Vec<SocketAddr>)>,
+        doh: Option<Url>,
+    ) -> Self {
+        self.resolve = resolve;
+        self.doh = doh;
+        self
+    }
+
+    /// Where download progress goes, [`ConsoleProgress`] by default.
+    pub fn progress(mut self, progress: Arc<dyn ProgressSink>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn templates(mut self, templates: Templates) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    pub fn blocklist(mut self, blocklist: Blocklist) -> Self {
+        self.blocklist = blocklist;
+        self
+    }
+
+    /// Asks for the password of the given user when a restored session expires
+    /// and no credentials are known.
+    pub fn password_prompt(mut self, prompt: Option<fn(&str) -> Option<String>>) -> Self {
+        self.password_prompt = prompt;
+        self
+    }
+
+    /// Sends requests through `proxy`, or directly when it is `None` so
+    /// reqwest does not pick up a proxy from the environment on its own, and
+    /// resolves hosts as set with [`ClientBuilder::dns`].
+    fn client_builder(&self, proxy: Option<&Proxy>) -> reqwest::ClientBuilder {
+        let mut client_builder = match proxy {
+            Some(proxy) => reqwest::Client::builder().proxy(proxy.clone()),
+            None => reqwest::Client::builder().no_proxy(),
+        };
+        for (host, addrs) in &self.resolve {
+            client_builder = client_builder.resolve_to_addrs(host, addrs);
+        }
+        if let Some(ref resolver) = self.dns_resolver {
+            client_builder = client_builder.dns_resolver(resolver.clone());
+        }
+        client_builder
+    }
+
+    /// The client the `Api` sends its calls through, bound by the connect and
+    /// total timeouts of API calls. Their read timeout is applied by
+    /// [`Client::call`].
+    fn api_client(&self) -> Result<reqwest::Client, Error> {
+        let mut client_builder = self.client_builder(self.api_proxy.as_ref());
+        if let Some(timeout) = self.timeouts.api.connect() {
+            client_builder = client_builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeouts.api.total() {
+            client_builder = client_builder.timeout(timeout);
+        }
+        Ok(client_builder.build()?)
+    }
+
+    fn http_client(&self, timeouts: &Timeouts) -> Result<reqwest::Client, Error> {
+        let mut client_builder = self.client_builder(self.cdn_proxy.as_ref());
+        if let Some(ref user_agent) = self.user_agent {
+            client_builder = client_builder.user_agent(user_agent.as_str());
+        }
+        if let Some(timeout) = timeouts.connect() {
+            client_builder = client_builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = timeouts.total() {
+            client_builder = client_builder.timeout(timeout);
+        }
+        Ok(client_builder.build()?)
+    }
+
+    pub fn build(mut self) -> Result<Client, Error> {
+        if let Some(ref url) = self.doh {
+            // Built before `dns_resolver` is set, so lookups go through the
+            // API proxy and the pinned hosts but not through the resolver.
+            let doh_client = self.client_builder(self.api_proxy.as_ref()).build()?;
+            self.dns_resolver = Some(Arc::new(DohResolver::new(url.clone(), doh_client)));
+        }
+        let client = Client {
+            api: Arc::new(StdRwLock::new(Arc::new(Api::new()))),
+            client: self.http_client(&self.timeouts.image)?,
+            game_client: self.http_client(&self.timeouts.game)?,
+            api_client: self.api_client()?,
+            image_quality: self.image_quality,
+            timeouts: self.timeouts,
+            image_server: self.image_server,
+            blocklist: Arc::new(self.blocklist),
+            templates: Arc::new(self.templates),
+            semaphore: self.concurrency.map(|n| Arc::new(Semaphore::new(n.max(1)))),
+            credentials: Arc::new(Mutex::new(None)),
+            password_prompt: self.password_prompt,
+            session: Arc::new(Mutex::new(None)),
+            refresh: Arc::new(tokio::sync::Mutex::new(())),
+            limiter: self
+                .rate_limit
+                .filter(|per_second| *per_second > 0.0)
+                .map(|per_second| Arc::new(RateLimiter::new(per_second, self.rate_jitter))),
+            bandwidth: self.bandwidth.map(|per_second| Arc::new(Bandwidth::new(per_second))),
+            retry: self.retry,
+            progress: self.progress,
+        };
+        *client.api.write().unwrap() = Arc::new(client.new_api()?);
+        Ok(client)
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Client {
+    /// A client with the default settings, see [`ClientBuilder`] for the others.
     pub fn new() -> Self {
-        Self {
-            api: Api::new(),
-            client: reqwest::Client::new(),
+        Self::builder().build().expect("default client settings are valid")
+    }
+
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+
+    /// Delay before retry number `attempt`, `None` once retries are used up.
+    pub fn retry_delay(&self, attempt: u32) -> Option<Duration> {
+        self.retry.delay(attempt)
+    }
+
+    /// The `Api` of the current session.
+    fn api(&self) -> Arc<Api> {
+        self.api.read().unwrap().clone()
+    }
+
+    /// Runs an `Api` call once the rate limit allows it. A call rejected
+    /// because the session expired is repeated once after logging in again,
+    /// one without an answer within the API read timeout fails as timed out.
+    ///
+    /// ```no_run
+    /// # async fn run(client: &picacg::client::Client) -> Result<(), libpicacg::error::Error> {
+    /// use futures::FutureExt;
+    ///
+    /// let cid = String::from("5821859b5f6b9a4f93dbf6e9");
+    /// let metadata = client.call(|api| api.comic_metadata(&cid).boxed()).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn call<'env, T, F>(&self, mut f: F) -> Result<T, Error>
+    where
+        F: for<'a> FnMut(&'a ApiCall<'env>) -> BoxFuture<'a, Result<T, Error>>,
+    {
+        let mut relogged = false;
+        loop {
+            self.throttle().await;
+            let api = ApiCall {
+                api: self.api(),
+                _env: PhantomData,
+            };
+            let result = match self.timeouts.api.read() {
+                Some(read) => tokio::time::timeout(read, f(&api)).await.unwrap_or_else(|_| {
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("no answer to an API call within {}s", read.as_secs()),
+                    )
+                    .into())
+                }),
+                None => f(&api).await,
+            };
+            match result {
+                Err(ref err) if is_auth_error(err) && !relogged && self.can_relogin() => {
+                    relogged = true;
+                    self.refresh_session(api.token()).await?;
+                }
+                result => return result,
+            }
         }
     }
 
-    pub async fn login(&mut self, email: &str, password: &str) -> Result<(), Error> {
-        self.api.login(email, password).await
+    /// The token calls are made with, `None` before logging in.
+    pub fn token(&self) -> Option<String> {
+        self.api().token().map(str::to_string)
+    }
+
+    /// Where download progress goes.
+    pub fn progress(&self) -> &dyn ProgressSink {
+        self.progress.as_ref()
+    }
+
+    /// Waits until the rate limit lets the next `Api` call through.
+    async fn throttle(&self) {
+        if let Some(ref limiter) = self.limiter {
+            limiter.wait().await;
+        }
+    }
+
+    /// A fresh `Api` with the proxy, timeout and image quality of this client.
+    fn new_api(&self) -> Result<Api, Error> {
+        let mut api = Api::new();
+        api.set_client(self.api_client.clone());
+        api.set_image_quality(self.image_quality.as_str());
+        Ok(api)
+    }
+
+    pub async fn login(&self, email: &str, password: &str) -> Result<(), Error> {
+        self.throttle().await;
+        let mut api = self.new_api()?;
+        api.login(email, password).await?;
+        *self.session.lock().unwrap() = api.token().map(|token| Session {
+            user: email.to_string(),
+            token: token.to_string(),
+            issued_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        });
+        *self.api.write().unwrap() = Arc::new(api);
+        *self.credentials.lock().unwrap() = Some((email.to_string(), password.to_string()));
+        Ok(())
+    }
+
+    /// Reuses a token from an earlier login instead of logging in.
+    pub fn restore_session(&self, session: Session) -> Result<(), Error> {
+        let mut api = self.new_api()?;
+        api.set_token(Some(session.token.clone()));
+        *self.api.write().unwrap() = Arc::new(api);
+        *self.session.lock().unwrap() = Some(session);
+        Ok(())
+    }
+
+    pub fn session(&self) -> Option<Session> {
+        self.session.lock().unwrap().clone()
+    }
+
+    /// Remembers the credentials used by [`Client::relogin`] without logging in now.
+    pub fn set_credentials(&self, email: &str, password: &str) {
+        *self.credentials.lock().unwrap() = Some((email.to_string(), password.to_string()));
+    }
+
+    pub fn can_relogin(&self) -> bool {
+        self.credentials.lock().unwrap().is_some()
+            || (self.session.lock().unwrap().is_some() && self.password_prompt.is_some())
+    }
+
+    /// Logs in again with the last known credentials to refresh the session token.
+    pub async fn relogin(&self) -> Result<(), Error> {
+        let credentials = self.credentials.lock().unwrap().clone();
+        let (email, password) = match credentials {
+            Some(credentials) => credentials,
+            None => {
+                let email = self.session().unwrap().user;
+                let password = (self.password_prompt.unwrap())(&email).unwrap_or_default();
+                (email, password)
+            }
+        };
+        self.login(&email, &password).await
+    }
+
+    /// Logs in again after a call was rejected with the `stale` token. Tasks
+    /// failing at the same time wait for a single login and then reuse its token.
+    pub async fn refresh_session(&self, stale: Option<&str>) -> Result<(), Error> {
+        let _refresh = self.refresh.lock().await;
+        if self.token().as_deref() != stale {
+            return Ok(());
+        }
+        self.relogin().await
     }
 
     pub fn get(&self, url: &str) -> RequestBuilder {
@@ -71,42 +662,48 @@ impl Client {
         self.client.post(url)
     }
 
-    fn reset_client(&mut self) -> Result<(), Error> {
-        let mut client_builder = ClientBuilder::new();
-        if let Some(proxy) = self.api.proxy() {
-            client_builder = client_builder.proxy(proxy.clone());
+    /// Where to download `media` from, honouring the image server override.
+    pub fn image_url(&self, media: &PictureDownloadResounce) -> String {
+        match self.image_server {
+            Some(ref server) => format!("{}/static/{}", server, media.path),
+            None => media.download_url(),
         }
-        if let Some(timeout) = self.api.timeout() {
-            client_builder = client_builder.timeout(timeout.clone());
-        }
-        self.client = client_builder.build()?;
-        Ok(())
     }
 
-    pub fn set_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
-        self.api.set_timeout(timeout.clone())?;
-        self.reset_client()?;
-        Ok(())
+    pub fn blocklist(&self) -> &Blocklist {
+        &self.blocklist
     }
 
-    pub fn set_proxy(&mut self, proxy: Option<Proxy>) -> Result<(), Error> {
-        self.api.set_proxy(proxy.clone())?;
-        self.reset_client()?;
-        Ok(())
+    /// Checks a comic against the blocklist, fetching its metadata only when needed.
+    pub async fn is_blocked(&self, cid: &str) -> Result<bool, Error> {
+        if self.blocklist.blocks_cid(cid) {
+            return Ok(true);
+        }
+        if !self.blocklist.needs_metadata() {
+            return Ok(false);
+        }
+        let metadata = self.call(|api| api.comic_metadata(cid).boxed()).await?;
+        Ok(self.blocklist.blocks_metadata(&metadata))
     }
 
     pub async fn game_download(&self, cid: &str, savedir: &str) -> Result<(), Error> {
-        let game_info = self.game_info(cid).await?;
+        if self.blocklist.blocks_cid(cid) {
+            eprintln!("Skipping blocked game {}", cid);
+            return Ok(());
+        }
+        let game_info = self.call(|api| api.game_info(cid).boxed()).await?;
         let output_dir = PathBuf::from_str(savedir).unwrap();
         if !output_dir.exists() {
             fs::create_dir_all(&output_dir).await?;
         }
         let mut file_path = output_dir.join(path_escape(&game_info.title));
+        let link = &game_info.android_links[0];
         let download_info = self
-            .game_download_info_get(&game_info.android_links[0])
+            .call(|api| api.game_download_info_get(link).boxed())
             .await?;
 
-        println!("{}", &download_info.description);
+        Console::clear_line();
+        eprintln!("{}", &download_info.description);
 
         let download_url = &download_info.download.node[0];
         file_path.set_extension(download_url.as_str().rsplit_once('.').unwrap().1);
@@ -118,21 +715,19 @@ impl Client {
             .to_string();
 
         let request = self
-            .client
+            .game_client
             .get(download_url)
             .header("referer", &game_info.android_links[0]);
 
         let request_head = self
-            .client
+            .game_client
             .head(download_url)
             .header("referer", &game_info.android_links[0]);
 
         let mut completed_length = Size::default();
-        let length = Size::from_byte(loop {
-            if let Ok(res) = request_head.try_clone().unwrap().send().await {
-                break res.headers().get(HeaderName::from_static("content-length")).unwrap().to_str().unwrap().parse::<u64>().unwrap();
-            }
-        });
+        let length = Size::from_byte(
+            content_length(&request_head, self.timeouts.game.read(), self.retry).await?,
+        );
         if file_path.exists() {
             completed_length.set_byte(file_path.metadata().unwrap().len() as u64);
         }
@@ -145,29 +740,24 @@ impl Client {
             .unwrap();
         let mut download_handle;
         'restart: loop {
-            download_handle = loop {
-                match request
-                    .try_clone()
-                    .unwrap()
-                    .timeout(Duration::from_secs(60 * 10))
-                    .header(
-                        "range",
-                        format!("bytes={}-", completed_length.as_byte()),
-                    )
-                    .send()
-                    .await
-                {
-                    Ok(handle) => {
-                        break handle;
-                    }
-                    Err(err) => {
-                        Console::clear_line();
-                        println!("{:?}", err);
-                        if err.is_timeout() || err.is_connect() || err.is_request() || err.is_body() {
-                            continue 'restart;
-                        } else {
-                            break 'restart;
-                        }
+            download_handle = match request
+                .try_clone()
+                .unwrap()
+                .header(
+                    "range",
+                    format!("bytes={}-", completed_length.as_byte()),
+                )
+                .send()
+                .await
+            {
+                Ok(handle) => handle,
+                Err(err) => {
+                    Console::clear_line();
+                    eprintln!("{:?}", err);
+                    if err.is_timeout() || err.is_connect() || err.is_request() || err.is_body() {
+                        continue 'restart;
+                    } else {
+                        return Err(err.into());
                     }
                 }
             };
@@ -176,73 +766,149 @@ impl Client {
             }
             let mut timer = Instant::now();
             while completed_length < length {
-                match download_handle.chunk().await {
+                let chunk = match read_chunk(&mut download_handle, self.timeouts.game.read()).await {
+                    Some(chunk) => chunk,
+                    None => continue 'restart,
+                };
+                match chunk {
                     Ok(chunk) => {
                         if let Some(chunk) = chunk {
                             completed_length += Size::from_byte(chunk.len() as u64);
-                            file_handle.write(&chunk).await.unwrap();
+                            file_handle.write_all(&chunk).await.unwrap();
+                            if let Some(ref bandwidth) = self.bandwidth {
+                                bandwidth.take(chunk.len()).await;
+                            }
                         }
                     }
                     Err(err) => {
                         Console::clear_line();
-                        println!("{:?}", err);
+                        eprintln!("{:?}", err);
                         if err.is_timeout() || err.is_connect() || err.is_request() || err.is_body() {
                             continue 'restart;
                         } else {
-                            break 'restart;
+                            return Err(err.into());
                         }
                     }
                 }
                 if timer.elapsed().as_secs() >= 1 {
-                    Console::clear_line();
-                    print!(
-                        "{}",
-                        Console::format_download_game(completed_length, length, &file_path_str)
-                    );
-                    stdout().flush().unwrap();
+                    self.progress.report(Progress::Game {
+                        completed: completed_length,
+                        length,
+                        path: &file_path_str,
+                    });
                     timer = Instant::now();
                 }
             }
         }
-        Console::clear_line();
+        self.progress.report(Progress::Finished {
+            name: &file_path_str,
+        });
         Ok(())
     }
 
     pub async fn comic_download_eps(&self, cid: &str, savedir: &str) -> Result<(), Error> {
-        let mut page_index = 1;
-        loop {
-            let eps = self.comic_eps(cid, page_index).await?;
-            for ep in eps.iter() {
-                self.comic_download_ep(cid, ep.order.unwrap(), savedir)
-                    .await?;
-            }
-            if !eps.has_next() {
-                break;
-            }
-            page_index = eps.next();
+        self.comic_download_selected_eps(cid, &EpSelector::All, savedir)
+            .await
+    }
+
+    pub async fn comic_download_selected_eps(
+        &self,
+        cid: &str,
+        selector: &EpSelector,
+        savedir: &str,
+    ) -> Result<(), Error> {
+        let Some(metadata) = self.unblocked_metadata(cid).await? else {
+            return Ok(());
+        };
+        let mut orders = Vec::new();
+        let mut eps = pager::items(
+            PageRange::all(),
+            move |page| self.call(move |api| api.comic_eps(cid, page).boxed()).boxed(),
+            |eps| eps.iter().filter_map(|ep| ep.order).collect(),
+        );
+        while let Some(order) = eps.next().await {
+            orders.push(order?);
+        }
+        for order in selector.select(&orders) {
+            self.download_pages(cid, &metadata, order, 1, None, savedir)
+                .await?;
         }
         Ok(())
     }
 
+    /// The metadata of a comic, `None` after reporting it as skipped when the
+    /// blocklist blocks it.
+    async fn unblocked_metadata(&self, cid: &str) -> Result<Option<ComicMetadata>, Error> {
+        if !self.blocklist.blocks_cid(cid) {
+            let metadata = self.call(|api| api.comic_metadata(cid).boxed()).await?;
+            if !self.blocklist.blocks_metadata(&metadata) {
+                return Ok(Some(metadata));
+            }
+        }
+        eprintln!("Skipping blocked comic {}", cid);
+        Ok(None)
+    }
+
     pub async fn comic_download_ep(
         &self,
         cid: &str,
         index: u64,
         savedir: &str,
     ) -> Result<(), Error> {
-        let mut page_index = 1;
-        let mut _comics_completed_total = Arc::new(RwLock::new(0));
-        loop {
-            let pages = self.comic_pages(cid, index, page_index).await?;
-            let metadata = self.comic_metadata(cid).await?;
-            let output_dir = PathBuf::from_str(savedir).unwrap().join(&format!(
-                "{} - {}",
-                &metadata.metadata.title, &metadata.metadata.author
-            ));
+        self.comic_download_pages(cid, index, 1, None, savedir)
+            .await
+    }
+
+    /// Downloads the images of episode `index` listed on pages `start..=end`,
+    /// or up to the last page when `end` is `None`.
+    pub async fn comic_download_pages(
+        &self,
+        cid: &str,
+        index: u64,
+        start: u64,
+        end: Option<u64>,
+        savedir: &str,
+    ) -> Result<(), Error> {
+        let Some(metadata) = self.unblocked_metadata(cid).await? else {
+            return Ok(());
+        };
+        self.download_pages(cid, &metadata, index, start, end, savedir)
+            .await
+    }
+
+    /// [`Client::comic_download_pages`] with the metadata of the comic at hand,
+    /// so the episodes of one comic share a single metadata call.
+    async fn download_pages(
+        &self,
+        cid: &str,
+        metadata: &ComicMetadata,
+        index: u64,
+        start: u64,
+        end: Option<u64>,
+        savedir: &str,
+    ) -> Result<(), Error> {
+        let mut completed_total = 0;
+        let mut failed = 0;
+        let mut stream = pager::pages(PageRange::new(start, end), move |page| {
+            self.call(move |api| api.comic_pages(cid, index, page).boxed()).boxed()
+        });
+        while let Some(pages) = stream.next().await {
+            let pages = pages?;
+            let order = index.to_string();
+            let values = [
+                ("title", metadata.metadata.title.as_str()),
+                ("author", metadata.metadata.author.as_str()),
+                ("cid", cid),
+                ("ep", pages.ep.title.as_str()),
+                ("order", order.as_str()),
+            ];
+            let output_dir = PathBuf::from_str(savedir)
+                .unwrap()
+                .join(Templates::render(&self.templates.comic_dir, &values[..3]));
             if !output_dir.exists() {
                 fs::create_dir_all(&output_dir).await?;
             }
-            let sub_savepath = output_dir.join(pages.ep.title.as_str());
+            let sub_savepath = output_dir.join(Templates::render(&self.templates.ep_dir, &values));
             if !sub_savepath.exists() {
                 fs::create_dir_all(&sub_savepath).await?;
             }
@@ -254,21 +920,29 @@ impl Client {
             );
             let mut _comics_total_length = Arc::new(RwLock::new(0));
             let mut _comics_completed_length = Arc::new(RwLock::new(0));
-            let mut _comics_downloaded = Arc::new(RwLock::new(0));
+            let mut tasks = JoinSet::new();
             for comic in pages.iter() {
-                let comic = comic;
-                let comics_downloaded = _comics_downloaded.clone();
                 let comics_completed_length = _comics_completed_length.clone();
                 let comics_total_length = _comics_total_length.clone();
-                let comics_completed_total = _comics_completed_total.clone();
                 let file_path = sub_savepath.join(path_escape(comic.media.filename()));
-                let download_url = comic.media.download_url();
+                let download_url = self.image_url(&comic.media);
                 let request = self.get(download_url.as_str());
                 let request_head = self.client.head(download_url.as_str());
-                tokio::spawn(async move {
-                    let length = loop {
-                        if let Ok(res) = request_head.try_clone().unwrap().send().await {
-                            break res.headers().get(HeaderName::from_static("content-length")).unwrap().to_str().unwrap().parse::<u64>().unwrap();
+                let semaphore = self.semaphore.clone();
+                let read_timeout = self.timeouts.image.read();
+                let retry = self.retry;
+                let bandwidth = self.bandwidth.clone();
+                tasks.spawn(async move {
+                    let _permit = match semaphore {
+                        Some(semaphore) => Some(semaphore.acquire_owned().await.unwrap()),
+                        None => None,
+                    };
+                    let length = match content_length(&request_head, read_timeout, retry).await {
+                        Ok(length) => length,
+                        Err(err) => {
+                            Console::clear_line();
+                            eprintln!("Failed to download {}: {}", file_path.display(), Console::format_error(&err));
+                            return Err(err);
                         }
                     };
                     *comics_total_length.write().await += length;
@@ -278,9 +952,7 @@ impl Client {
                         *comics_completed_length.write().await += completed_length;
                     }
                     if completed_length == length {
-                        *comics_downloaded.write().await += 1;
-                        *comics_completed_total.write().await += 1;
-                        return;
+                        return Ok(());
                     }
                     let mut download_handle;
                     let mut file_handle = fs::OpenOptions::new()
@@ -291,79 +963,93 @@ impl Client {
                             .await
                             .unwrap();
                     'restart: loop {
-                        download_handle = loop {
-                            match request
-                                .try_clone()
-                                .unwrap()
-                                .header(
-                                    "range",
-                                    format!("bytes={}-", completed_length),
-                                )
-                                .send()
-                                .await
-                            {
-                                Ok(handle) => {
-                                    break handle;
-                                }
-                                Err(err) => {
-                                    Console::clear_line();
-                                    println!("{:?}", err);
-                                    if err.is_timeout() || err.is_connect() || err.is_request() || err.is_body() {
-                                        continue 'restart;
-                                    } else {
-                                        break 'restart;
-                                    }
+                        download_handle = match request
+                            .try_clone()
+                            .unwrap()
+                            .header(
+                                "range",
+                                format!("bytes={}-", completed_length),
+                            )
+                            .send()
+                            .await
+                        {
+                            Ok(handle) => handle,
+                            Err(err) => {
+                                Console::clear_line();
+                                eprintln!("{:?}", err);
+                                if err.is_timeout() || err.is_connect() || err.is_request() || err.is_body() {
+                                    continue 'restart;
+                                } else {
+                                    return Err(err.into());
                                 }
                             }
                         };
                         while completed_length < length {
-                            match download_handle.chunk().await {
+                            let chunk = match read_chunk(&mut download_handle, read_timeout).await {
+                                Some(chunk) => chunk,
+                                None => continue 'restart,
+                            };
+                            match chunk {
                                 Ok(chunk) => {
                                     if let Some(chunk) = chunk {
                                         completed_length += chunk.len() as u64;
                                         *comics_completed_length.write().await += chunk.len() as u64;
-                                        file_handle.write(&chunk).await.unwrap();
+                                        file_handle.write_all(&chunk).await.unwrap();
+                                        if let Some(ref bandwidth) = bandwidth {
+                                            bandwidth.take(chunk.len()).await;
+                                        }
                                     }
                                 }
                                 Err(err) => {
                                     Console::clear_line();
-                                    println!("{:?}", err);
+                                    eprintln!("{:?}", err);
                                     if err.is_timeout() || err.is_connect() || err.is_request() || err.is_body() {
                                         continue 'restart;
                                     } else {
-                                        break 'restart;
+                                        return Err(err.into());
                                     }
                                 }
                             }
                         }
                         break;
                     }
-                    *comics_downloaded.write().await += 1;
-                    *comics_completed_total.write().await += 1;
+                    Ok(())
                 });
             }
-            while *_comics_downloaded.read().await < pages.len() {
-                Console::clear_line();
-                print!(
-                    "{}",
-                    Console::format_download_ep(
-                        &downloading_name,
-                        pages.current(),
-                        pages.pages,
-                        *_comics_downloaded.read().await as u64 + 1,
-                        pages.len() as u64,
-                        *_comics_completed_total.read().await + 1,
-                        pages.total
-                    )
-                );
-                stdout().flush().unwrap();
-                tokio::time::sleep(Duration::from_millis(50)).await;
-            }
-            Console::clear_line();
-            if !pages.has_next() {
-                break;
+            // Every task ends in a result, so the page is over once all are joined.
+            let mut downloaded = 0;
+            loop {
+                self.progress.report(Progress::Episode {
+                    name: &downloading_name,
+                    page: pages.current(),
+                    pages: pages.pages,
+                    image: downloaded + 1,
+                    images: pages.len() as u64,
+                    downloaded: completed_total + 1,
+                    total: pages.total,
+                });
+                match tokio::time::timeout(Duration::from_millis(50), tasks.join_next()).await {
+                    Ok(Some(result)) => {
+                        downloaded += 1;
+                        completed_total += 1;
+                        if !matches!(result, Ok(Ok(()))) {
+                            failed += 1;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(_) => {}
+                }
             }
-            page_index = pages.next();
+            self.progress.report(Progress::Finished {
+                name: &downloading_name,
+            });
+        }
+        if failed > 0 {
+            return Err(io::Error::other(format!(
+                "{} images of episode {} of comic {} failed to download",
+                failed, index, cid
+            ))
+            .into());
         }
         Ok(())
     }
@@ -1,21 +1,237 @@
-use clap::{ArgAction, Parser};
+use std::time::Duration;
+
+use clap::{ArgAction, Parser, ValueEnum};
+use libpicacg::Sort;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    blocklist::Blocklist,
+    config::{Config, DownloadTimeouts, RetryPolicy, TimeoutKind, Timeouts},
+    dns::DnsOptions,
+    filter::Filter,
+    output::Format,
+    pager::{PageRange, Paging},
+    parse::{parse_comic_id, parse_game_id},
+    proxy::ProxyOptions,
+    selector::EpSelector,
+};
 
 #[derive(Parser, Debug, Clone)]
 pub struct GlobalOptions {
-    #[clap(short = 'a', long = "all-proxy")]
-    pub all_proxy: Option<String>,
-    #[clap(long = "http-proxy")]
-    pub http_proxy: Option<String>,
-    #[clap(long = "https-proxy")]
-    pub https_proxy: Option<String>,
+    /// Account from `profiles` in the config to use
+    #[clap(long = "profile", env = "PICACG_PROFILE")]
+    pub profile: Option<String>,
+    /// User to log in as, instead of the configured one
+    #[clap(long = "user", env = "PICACG_USER")]
+    pub user: Option<String>,
+    /// Reads the password from the first line of stdin
+    #[clap(long = "password-stdin", default_value = "false", action = ArgAction::SetTrue)]
+    pub password_stdin: bool,
+    /// Fails instead of prompting for a user or password.
+    /// PICACG_PASSWORD and PICACG_TOKEN are used when set
+    #[clap(long = "no-interactive", default_value = "false", action = ArgAction::SetTrue)]
+    pub no_interactive: bool,
+    /// API calls per second, separate from --concurrency [default: unlimited]
+    #[clap(long = "rate-limit", value_parser = parse_rate)]
+    pub rate_limit: Option<f64>,
+    /// Random extra delay of up to this many milliseconds per API call
+    /// [default: a quarter of the interval]
+    #[clap(long = "rate-jitter")]
+    pub rate_jitter: Option<u64>,
+    /// Download speed shared by all downloads, in bytes a second with an
+    /// optional K, M or G suffix such as 500K [default: unlimited]
+    #[clap(long = "bandwidth", value_parser = parse_bandwidth)]
+    pub bandwidth: Option<u64>,
+    /// Retries of API calls failing with network or server errors [default: 5]
+    #[clap(long = "retries")]
+    pub retries: Option<u32>,
+    /// Retry policy from the config with --retries applied
+    #[clap(skip)]
+    pub retry: RetryPolicy,
+    #[clap(flatten)]
+    pub proxy: ProxyOptions,
+    #[clap(flatten)]
+    pub dns: DnsOptions,
+    /// Image quality requested from the API [default: original]
+    #[clap(long = "image-quality", value_enum)]
+    pub image_quality: Option<ImageQuality>,
+    /// Image server base URL replacing the one the API hands out
+    #[clap(long = "image-server", value_parser = parse_image_server)]
+    pub image_server: Option<String>,
     #[clap(subcommand)]
     pub subcommand: SubCommand,
-    #[clap(short = 'o', long = "save-dir", default_value = ".")]
-    pub save_dir: String,
+    #[clap(short = 'o', long = "save-dir")]
+    pub save_dir: Option<String>,
     #[clap(short='d', long="download", default_value="false", action=ArgAction::SetTrue)]
     pub download: bool,
+    /// Only lists, even when the config turns on `download`
+    #[clap(long = "no-download", default_value = "false", action = ArgAction::SetTrue, overrides_with = "download")]
+    pub no_download: bool,
+    /// Makes listings fetch every page unless --until, --pages or --limit is given
     #[clap(short='e', long="end", default_value="false", action=ArgAction::SetTrue)]
     pub until_end: bool,
+    /// Fetches only the start page, even when the config turns on `until_end`
+    #[clap(long = "no-end", default_value = "false", action = ArgAction::SetTrue, overrides_with = "until_end")]
+    pub no_until_end: bool,
+    #[clap(short = 'f', long = "format", value_enum)]
+    pub format: Option<Format>,
+    /// Cap on a whole API call in seconds, reading the answer included [default: 5]
+    #[clap(long = "timeout")]
+    pub timeout: Option<u64>,
+    /// Connect timeout in seconds, of everything or as api=S, image=S or game=S
+    /// of one kind [default: 10]
+    #[clap(long = "connect-timeout", value_parser = parse_timeout, action = ArgAction::Append)]
+    pub connect_timeout: Vec<(Option<TimeoutKind>, u64)>,
+    /// Seconds without data before a download or its HEAD request is restarted,
+    /// or without an answer before an API call is retried, of everything or as
+    /// api=S, image=S or game=S of one kind [default: api none, images 30, games 60]
+    #[clap(long = "read-timeout", value_parser = parse_timeout, action = ArgAction::Append)]
+    pub read_timeout: Vec<(Option<TimeoutKind>, u64)>,
+    /// Cap on a single image or game download in seconds, or as image=S or
+    /// game=S of one kind, api=S is the same as --timeout [default: none]
+    #[clap(long = "download-timeout", value_parser = parse_timeout, action = ArgAction::Append)]
+    pub download_timeout: Vec<(Option<TimeoutKind>, u64)>,
+    /// Timeouts from the config with the ones above applied
+    #[clap(skip)]
+    pub timeouts: DownloadTimeouts,
+    /// Images downloaded at the same time [default: unlimited]
+    #[clap(long = "concurrency")]
+    pub concurrency: Option<usize>,
+    /// Comics, episodes or games downloaded at the same time [default: 2]
+    #[clap(short = 'j', long = "jobs")]
+    pub jobs: Option<usize>,
+    #[clap(flatten)]
+    pub filter: Filter,
+}
+
+impl GlobalOptions {
+    /// Fills every option not given on the command line from `config`, and
+    /// proxies from the environment when neither sets them.
+    pub fn apply_config(&mut self, config: &Config) {
+        self.proxy = self
+            .proxy
+            .clone()
+            .or(&config.proxy)
+            .or(&ProxyOptions::from_env());
+        self.dns = self.dns.clone().or(&config.dns);
+        self.image_quality = self.image_quality.or(config.image_quality);
+        self.image_server = self.image_server.take().or_else(|| config.image_server.clone());
+        self.save_dir = self.save_dir.take().or_else(|| config.save_dir.clone());
+        self.download = !self.no_download && (self.download || config.download);
+        self.until_end = !self.no_until_end && (self.until_end || config.until_end);
+        self.format = self.format.or(config.format);
+        self.timeouts = config.timeouts;
+        if let Some(timeout) = config.timeout {
+            self.timeouts.api.total = Some(timeout);
+        }
+        let every = [TimeoutKind::Api, TimeoutKind::Image, TimeoutKind::Game];
+        apply_timeouts(&mut self.timeouts, &self.connect_timeout, &every, |timeouts| &mut timeouts.connect);
+        apply_timeouts(&mut self.timeouts, &self.read_timeout, &every, |timeouts| &mut timeouts.read);
+        apply_timeouts(
+            &mut self.timeouts,
+            &self.download_timeout,
+            &[TimeoutKind::Image, TimeoutKind::Game],
+            |timeouts| &mut timeouts.total,
+        );
+        if let Some(timeout) = self.timeout {
+            self.timeouts.api.total = Some(timeout);
+        }
+        self.concurrency = self.concurrency.or(config.concurrency);
+        self.jobs = self.jobs.or(config.jobs);
+        self.rate_limit = self.rate_limit.or(config.rate_limit);
+        self.rate_jitter = self.rate_jitter.or(config.rate_jitter);
+        self.bandwidth = self.bandwidth.or(config.bandwidth);
+        self.retry = config.retry;
+        if let Some(retries) = self.retries {
+            self.retry.attempts = retries;
+        }
+        self.filter = self.filter.clone().or(&config.filter);
+    }
+
+    pub fn save_dir(&self) -> &str {
+        self.save_dir.as_deref().unwrap_or(".")
+    }
+
+    pub fn format(&self) -> Format {
+        self.format.unwrap_or_default()
+    }
+
+    /// Pages of a listing selected by `paging`, see [`Paging::range`].
+    pub fn page_range(&self, paging: &Paging) -> PageRange {
+        paging.range(self.until_end)
+    }
+
+    pub fn jobs(&self) -> usize {
+        self.jobs.unwrap_or(2).max(1)
+    }
+
+    pub fn rate_jitter(&self) -> Option<Duration> {
+        self.rate_jitter.map(Duration::from_millis)
+    }
+}
+
+/// Sets one field of `timeouts` picked by `field` to each of `values`, a value
+/// without a kind for every kind in `every`. Values for one kind go last, so
+/// they win over the ones for every kind.
+fn apply_timeouts(
+    timeouts: &mut DownloadTimeouts,
+    values: &[(Option<TimeoutKind>, u64)],
+    every: &[TimeoutKind],
+    field: fn(&mut Timeouts) -> &mut Option<u64>,
+) {
+    let general = values.iter().filter(|(kind, _)| kind.is_none());
+    let specific = values.iter().filter(|(kind, _)| kind.is_some());
+    for &(kind, seconds) in general.chain(specific) {
+        match kind {
+            Some(kind) => *field(timeouts.get_mut(kind)) = Some(seconds),
+            None => {
+                for &kind in every {
+                    *field(timeouts.get_mut(kind)) = Some(seconds);
+                }
+            }
+        }
+    }
+}
+
+fn parse_timeout(value: &str) -> Result<(Option<TimeoutKind>, u64), String> {
+    let (kind, seconds) = match value.split_once('=') {
+        Some(("api", seconds)) => (Some(TimeoutKind::Api), seconds),
+        Some(("image", seconds)) => (Some(TimeoutKind::Image), seconds),
+        Some(("game", seconds)) => (Some(TimeoutKind::Game), seconds),
+        Some((kind, _)) => return Err(format!("unknown kind `{}`, expected api, image or game", kind)),
+        None => (None, value),
+    };
+    match seconds.parse() {
+        Ok(seconds) => Ok((kind, seconds)),
+        Err(_) => Err(format!("`{}` is not a number of seconds", seconds)),
+    }
+}
+
+fn parse_rate(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        _ => Err(format!("`{}` is not a positive number of calls per second", value)),
+    }
+}
+
+fn parse_bandwidth(value: &str) -> Result<u64, String> {
+    let invalid = || format!("`{}` is not a number of bytes a second such as 800K or 2M", value);
+    let (number, unit) = match value.trim().char_indices().last() {
+        Some((index, suffix)) if suffix.is_ascii_alphabetic() => {
+            let unit = match suffix.to_ascii_uppercase() {
+                'K' => 1 << 10,
+                'M' => 1 << 20,
+                'G' => 1 << 30,
+                _ => return Err(invalid()),
+            };
+            (&value.trim()[..index], unit)
+        }
+        _ => (value.trim(), 1),
+    };
+    match number.parse::<f64>() {
+        Ok(number) if number > 0.0 && number.is_finite() => Ok((number * unit as f64).max(1.0) as u64),
+        _ => Err(invalid()),
+    }
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -26,95 +242,190 @@ pub enum SubCommand {
     Game(GameOptions),
     #[clap(subcommand)]
     User(UserOptions),
+    #[clap(subcommand)]
+    Blocklist(BlocklistOptions),
+    #[clap(subcommand)]
+    Config(ConfigOptions),
+    /// The journal of downloads, kept so interrupted runs can be resumed
+    #[clap(subcommand)]
+    Queue(QueueOptions),
+}
+
+impl SubCommand {
+    /// Whether the command talks to the API and so needs a logged in account.
+    pub fn needs_account(&self) -> bool {
+        !matches!(
+            self,
+            SubCommand::Blocklist(_)
+                | SubCommand::Config(_)
+                | SubCommand::User(UserOptions::Logout)
+                | SubCommand::Queue(QueueOptions::List | QueueOptions::Clear { .. })
+        )
+    }
+
+    /// Whether the command downloads even without `--download`.
+    pub fn downloads(&self) -> bool {
+        matches!(
+            self,
+            SubCommand::Comic(ComicOptions::Download { .. })
+                | SubCommand::Game(GameOptions::Download { .. })
+                | SubCommand::Queue(QueueOptions::Resume | QueueOptions::RetryFailed)
+        )
+    }
 }
 
 #[derive(Parser, Debug, Clone)]
 pub enum ComicOptions {
-    Ranking,
+    Ranking {
+        /// Stops after this many comics
+        #[clap(long = "limit")]
+        limit: Option<usize>,
+    },
     Metadata {
-        #[clap(short='c', long="cids", action=ArgAction::Append)]
+        #[clap(short='c', long="cids", action=ArgAction::Append, value_parser=parse_comic_id)]
         cids: Vec<String>,
+        #[clap(short = 'i', long = "input")]
+        input: Option<String>,
         #[clap(short = 'o', long = "save-dir", default_value = ".")]
         save_dir: String,
     },
     Recommended {
-        #[clap(short='c', long="cids", action=ArgAction::Append)]
+        #[clap(short='c', long="cids", action=ArgAction::Append, value_parser=parse_comic_id)]
         cids: Vec<String>,
+        /// Stops after this many comics
+        #[clap(long = "limit")]
+        limit: Option<usize>,
         #[clap(short = 'o', long = "save-dir", default_value = ".")]
         save_dir: String,
     },
     Eps {
-        #[clap(short = 'c', long = "cid")]
+        #[clap(short = 'c', long = "cid", value_parser = parse_comic_id)]
         cid: String,
-        #[clap(short = 's', long = "start", default_value = "1")]
-        start: u64,
-        #[clap(short = 'u', long = "until")]
-        end: Option<u64>,
+        #[clap(flatten)]
+        paging: Paging,
         #[clap(short = 'o', long = "save-dir", default_value = ".")]
         save_dir: String,
     },
     Pages {
-        #[clap(short = 'c', long = "cid")]
+        #[clap(short = 'c', long = "cid", value_parser = parse_comic_id)]
         cid: String,
         #[clap(long = "start-index", default_value = "1")]
         start_index: u64,
         #[clap(long = "until-index")]
         end_index: Option<u64>,
-        #[clap(short = 's', long = "start", default_value = "1")]
-        start: u64,
-        #[clap(short = 'u', long = "until")]
-        end: Option<u64>,
+        #[clap(flatten)]
+        paging: Paging,
         #[clap(short = 'o', long = "save-dir", default_value = ".")]
         save_dir: String,
     },
     PicLikeGet {
-        #[clap(short = 'c', long = "cid")]
+        #[clap(short = 'c', long = "cid", value_parser = parse_comic_id)]
         cid: String,
-        #[clap(short = 's', long = "start", default_value = "1")]
-        start: u64,
-        #[clap(short = 'u', long = "until")]
-        end: Option<u64>,
+        #[clap(flatten)]
+        paging: Paging,
         #[clap(short = 'o', long = "save-dir", default_value = ".")]
         save_dir: String,
     },
     Search {
         #[clap(short = 'k', long = "keyword")]
         keyword: String,
-        #[clap(short = 's', long = "start", default_value = "1")]
-        start: u64,
-        #[clap(short = 'u', long = "until")]
-        end: Option<u64>,
+        /// [default: max-like]
+        #[clap(long = "sort", value_enum)]
+        sort: Option<SortOption>,
+        #[clap(long = "category", action=ArgAction::Append)]
+        categories: Vec<String>,
+        #[clap(flatten)]
+        paging: Paging,
         #[clap(short = 'o', long = "save-dir", default_value = ".")]
         save_dir: String,
     },
     Favourites {
-        #[clap(short = 's', long = "start", default_value = "1")]
-        start: u64,
-        #[clap(short = 'u', long = "until")]
-        end: Option<u64>,
+        /// [default: desc-by-date]
+        #[clap(long = "sort", value_enum)]
+        sort: Option<SortOption>,
+        #[clap(flatten)]
+        paging: Paging,
         #[clap(short = 'o', long = "save-dir", default_value = ".")]
         save_dir: String,
     },
     Download {
-        #[clap(short = 'c', long = "cid")]
+        #[clap(short = 'c', long = "cid", value_parser = parse_comic_id)]
         cids: Vec<String>,
+        #[clap(short = 'i', long = "input")]
+        input: Option<String>,
+        #[clap(long = "eps", conflicts_with_all = ["first", "latest"])]
+        eps: Option<EpSelector>,
+        #[clap(long = "first", conflicts_with = "latest")]
+        first: Option<u64>,
+        #[clap(long = "latest")]
+        latest: Option<u64>,
         #[clap(short = 'o', long = "save-dir", default_value = ".")]
         save_dir: String,
     },
 }
 
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOption {
+    Default,
+    DescByDate,
+    AscByDate,
+    MaxLike,
+    MaxView,
+}
+
+impl From<SortOption> for Sort {
+    fn from(value: SortOption) -> Self {
+        match value {
+            SortOption::Default => Sort::Default,
+            SortOption::DescByDate => Sort::DescByDate,
+            SortOption::AscByDate => Sort::AscByDate,
+            SortOption::MaxLike => Sort::MaxLike,
+            SortOption::MaxView => Sort::MaxView,
+        }
+    }
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageQuality {
+    #[default]
+    Original,
+    High,
+    Medium,
+    Low,
+}
+
+impl ImageQuality {
+    /// Value of the `image-quality` header sent to the API.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageQuality::Original => "original",
+            ImageQuality::High => "high",
+            ImageQuality::Medium => "medium",
+            ImageQuality::Low => "low",
+        }
+    }
+}
+
+pub fn parse_image_server(value: &str) -> Result<String, String> {
+    let url = reqwest::Url::parse(value).map_err(|err| format!("`{}` is not a URL: {}", value, err))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err(format!("image server `{}` must be an http(s) URL", value));
+    }
+    Ok(value.trim_end_matches('/').to_string())
+}
+
 #[derive(Parser, Debug, Clone)]
 pub enum GameOptions {
     Games {
-        #[clap(short = 's', long = "start", default_value = "1")]
-        start: u64,
-        #[clap(short = 'u', long = "until")]
-        end: Option<u64>,
+        #[clap(flatten)]
+        paging: Paging,
         #[clap(short = 'o', long = "save-dir", default_value = ".")]
         save_dir: String,
     },
     Info {
-        #[clap(short='c', long="cids", action=ArgAction::Append)]
+        #[clap(short='c', long="cids", action=ArgAction::Append, value_parser=parse_game_id)]
         cids: Vec<String>,
         #[clap(short='d', long="description", action=ArgAction::SetTrue, default_value="false")]
         description: bool,
@@ -122,8 +433,10 @@ pub enum GameOptions {
         save_dir: String,
     },
     Download {
-        #[clap(short='c', long="cids", action=ArgAction::Append)]
+        #[clap(short='c', long="cids", action=ArgAction::Append, value_parser=parse_game_id)]
         cids: Vec<String>,
+        #[clap(short = 'i', long = "input")]
+        input: Option<String>,
         #[clap(short = 'o', long = "save-dir", default_value = ".")]
         save_dir: String,
     },
@@ -131,8 +444,47 @@ pub enum GameOptions {
 
 #[derive(Parser, Debug, Clone)]
 pub enum UserOptions {
-    PunchIn,
+    PunchIn {
+        /// Punches in the default account and every profile with its own cached
+        /// session, prompting for the password of any account without a valid
+        /// one. PICACG_TOKEN and PICACG_PASSWORD are ignored, --password-stdin
+        /// is rejected
+        #[clap(long = "all-profiles", default_value = "false", action = ArgAction::SetTrue)]
+        all_profiles: bool,
+    },
     Profile,
+    /// Removes the cached session tokens of every profile and any stored password
+    Logout,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub enum BlocklistOptions {
+    Add(Blocklist),
+    Remove(Blocklist),
+    List,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub enum ConfigOptions {
+    Get { key: String },
+    Set { key: String, value: String },
+    List,
+    Edit,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub enum QueueOptions {
+    /// Runs the jobs left pending by an earlier run
+    Resume,
+    List,
+    /// Runs the failed jobs again
+    RetryFailed,
+    /// Removes done and failed jobs
+    Clear {
+        /// Removes pending jobs as well
+        #[clap(long = "all", default_value = "false", action = ArgAction::SetTrue)]
+        all: bool,
+    },
 }
 
 pub enum DownloadParmas {
@@ -0,0 +1,436 @@
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{blocklist::Blocklist, dns::DnsOptions, command::{ImageQuality, SortOption}, filter::Filter, output::Format, proxy::ProxyOptions};
+
+/// Path of the config file relative to `$HOME`, read and written through `Configer`.
+pub const CONFIG_PATH: &str = ".config/picacg/config";
+/// Path of the cached session token relative to `$HOME`.
+pub const SESSION_PATH: &str = ".config/picacg/session";
+/// Path of the download queue journal relative to `$HOME`.
+pub const QUEUE_PATH: &str = ".config/picacg/queue";
+/// Directory of the download queue journals of named profiles relative to `$HOME`.
+pub const PROFILE_QUEUES_PATH: &str = ".config/picacg/queues";
+
+/// A profile name usable in a file name, it never contains a dot.
+fn file_name(profile: &str) -> String {
+    profile
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// Session path of a named profile, the default account uses [`SESSION_PATH`].
+pub fn session_path(profile: Option<&str>) -> String {
+    match profile {
+        Some(name) => format!("{}.{}", SESSION_PATH, file_name(name)),
+        None => SESSION_PATH.to_string(),
+    }
+}
+
+/// Queue journal path of a named profile, the default account uses [`QUEUE_PATH`].
+pub fn queue_path(profile: Option<&str>) -> String {
+    match profile {
+        Some(name) => format!("{}/{}", PROFILE_QUEUES_PATH, file_name(name)),
+        None => QUEUE_PATH.to_string(),
+    }
+}
+
+/// Resolves a path relative to `$HOME`, like the ones passed to `Configer`.
+pub fn home_path(relative: &str) -> PathBuf {
+    PathBuf::from(env::var("HOME").unwrap()).join(relative)
+}
+
+/// Creates `path` readable and writable by its owner only, or restricts it
+/// when it exists, so whatever is written to it next is never exposed.
+pub fn create_private(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        let file = options.open(path)?;
+        let mut permissions = file.metadata()?.permissions();
+        if permissions.mode() & 0o777 != 0o600 {
+            permissions.set_mode(0o600);
+            file.set_permissions(permissions)?;
+        }
+    }
+    #[cfg(not(unix))]
+    options.open(path)?;
+    Ok(())
+}
+
+/// Removes the cached sessions of the default account and of every profile,
+/// including profiles no longer in the config.
+pub fn remove_sessions() -> io::Result<()> {
+    let session = home_path(SESSION_PATH);
+    let (Some(dir), Some(name)) = (session.parent(), session.file_name()) else {
+        return Ok(());
+    };
+    let name = name.to_string_lossy();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if file_name == name || file_name.starts_with(&format!("{}.", name)) {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// A token handed out by a login, reused until the API rejects it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub user: String,
+    pub token: String,
+    /// Unix timestamp of the login that issued the token.
+    pub issued_at: u64,
+}
+
+/// Directory names used when saving comics.
+///
+/// `{title}`, `{author}` and `{cid}` are available in both, `{ep}` (the episode
+/// title) and `{order}` only in `ep_dir`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Templates {
+    pub comic_dir: String,
+    pub ep_dir: String,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Self {
+            comic_dir: "{title} - {author}".to_string(),
+            ep_dir: "{ep}".to_string(),
+        }
+    }
+}
+
+impl Templates {
+    pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+        let mut rendered = template.to_string();
+        for (key, value) in values {
+            rendered = rendered.replace(&format!("{{{}}}", key), value);
+        }
+        rendered
+    }
+}
+
+/// Timeouts of one kind of request in seconds, `null` disables one.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct Timeouts {
+    /// Time to establish a connection.
+    pub connect: Option<u64>,
+    /// Time without receiving any bytes before the download is restarted. The
+    /// `Api` reads its small answers whole, so for API calls this is the time
+    /// to wait for the answer, after which the call is retried.
+    pub read: Option<u64>,
+    /// Cap on a whole request.
+    pub total: Option<u64>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Some(10),
+            read: Some(30),
+            total: None,
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn connect(&self) -> Option<Duration> {
+        self.connect.map(Duration::from_secs)
+    }
+
+    pub fn read(&self) -> Option<Duration> {
+        self.read.map(Duration::from_secs)
+    }
+
+    pub fn total(&self) -> Option<Duration> {
+        self.total.map(Duration::from_secs)
+    }
+}
+
+/// Timeouts of API calls, image downloads and game downloads. `Config.timeout`,
+/// when set, replaces `api.total`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct DownloadTimeouts {
+    pub api: Timeouts,
+    pub image: Timeouts,
+    pub game: Timeouts,
+}
+
+impl DownloadTimeouts {
+    pub fn get_mut(&mut self, kind: TimeoutKind) -> &mut Timeouts {
+        match kind {
+            TimeoutKind::Api => &mut self.api,
+            TimeoutKind::Image => &mut self.image,
+            TimeoutKind::Game => &mut self.game,
+        }
+    }
+}
+
+/// What a [`Timeouts`] applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutKind {
+    Api,
+    Image,
+    Game,
+}
+
+impl Default for DownloadTimeouts {
+    fn default() -> Self {
+        Self {
+            api: Timeouts {
+                read: None,
+                total: Some(5),
+                ..Timeouts::default()
+            },
+            image: Timeouts::default(),
+            game: Timeouts {
+                read: Some(60),
+                ..Timeouts::default()
+            },
+        }
+    }
+}
+
+/// How failed API calls are retried, see [`crate::client::classify`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt.
+    pub attempts: u32,
+    /// Delay before the first retry in milliseconds, doubled on every retry.
+    pub backoff: u64,
+    /// Upper bound of the delay in milliseconds.
+    pub max_backoff: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            backoff: 1000,
+            max_backoff: 30_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `attempt`, counted from 0.
+    pub fn delay(&self, attempt: u32) -> Option<Duration> {
+        if attempt >= self.attempts {
+            return None;
+        }
+        let backoff = self.backoff.saturating_mul(1 << attempt.min(20));
+        Some(Duration::from_millis(backoff.min(self.max_backoff)))
+    }
+}
+
+/// A named account in `profiles`, selected with `--profile`.
+///
+/// Proxies and the save directory fall back to the top-level ones when unset.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Account {
+    pub user: String,
+    #[serde(flatten)]
+    pub proxy: ProxyOptions,
+    pub save_dir: Option<String>,
+}
+
+/// Everything read from the config file. Unset values fall back to the
+/// command line defaults; flags given on the command line always win.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Config {
+    pub user: String,
+    /// Only read to migrate old configs, passwords are never written back.
+    #[serde(skip_serializing)]
+    pub password: String,
+    #[serde(flatten)]
+    pub proxy: ProxyOptions,
+    pub save_dir: Option<String>,
+    pub download: bool,
+    pub until_end: bool,
+    pub format: Option<Format>,
+    /// Timeout of an API call in seconds, replaces `timeouts.api.total`.
+    pub timeout: Option<u64>,
+    pub timeouts: DownloadTimeouts,
+    pub dns: DnsOptions,
+    pub image_quality: Option<ImageQuality>,
+    /// Base URL replacing the image server picked by the API.
+    pub image_server: Option<String>,
+    /// Images downloaded at the same time, unlimited when unset.
+    pub concurrency: Option<usize>,
+    /// Downloads running at the same time, 2 when unset.
+    pub jobs: Option<usize>,
+    /// API calls per second, unlimited when unset.
+    pub rate_limit: Option<f64>,
+    /// Random extra delay per API call in milliseconds.
+    pub rate_jitter: Option<u64>,
+    /// Bytes a second all downloads may receive together, unlimited when unset.
+    pub bandwidth: Option<u64>,
+    pub retry: RetryPolicy,
+    pub search_sort: Option<SortOption>,
+    pub favourites_sort: Option<SortOption>,
+    pub filter: Filter,
+    pub templates: Templates,
+    pub blocklist: Blocklist,
+    pub profiles: BTreeMap<String, Account>,
+}
+
+impl Config {
+    /// The config as seen by `profile`, its account replacing the top-level one.
+    pub fn with_profile(&self, profile: Option<&str>) -> Result<Config, String> {
+        let Some(name) = profile else {
+            return Ok(self.clone());
+        };
+        let account = self
+            .profiles
+            .get(name)
+            .ok_or_else(|| format!("unknown profile `{}`", name))?;
+        let mut config = self.clone();
+        config.user = account.user.clone();
+        config.password.clear();
+        config.proxy = account.proxy.clone().or(&config.proxy);
+        config.save_dir = account.save_dir.clone().or(config.save_dir);
+        Ok(config)
+    }
+
+    /// Looks up a dotted key such as `templates.comic_dir`.
+    pub fn get(&self, key: &str) -> Option<Value> {
+        let value = serde_json::to_value(self).unwrap();
+        value
+            .pointer(&format!("/{}", key.replace('.', "/")))
+            .cloned()
+    }
+
+    /// Sets a dotted key, `value` is read as JSON and falls back to a plain string.
+    ///
+    /// Keys under `profiles.<name>` create the profile when it does not exist yet.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let mut base = self.clone();
+        if let Some(name) = key.strip_prefix("profiles.").and_then(|rest| rest.split('.').next()) {
+            base.profiles.entry(name.to_string()).or_default();
+        }
+        let mut config = serde_json::to_value(&base).unwrap();
+        let slot = config
+            .pointer_mut(&format!("/{}", key.replace('.', "/")))
+            .ok_or_else(|| format!("unknown config key `{}`", key))?;
+        *slot = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+        *self = serde_json::from_value(config)
+            .map_err(|err| format!("invalid value for `{}`: {}", key, err))?;
+        Ok(())
+    }
+
+    /// Every leaf of the config as `(dotted key, value)`.
+    pub fn entries(&self) -> Vec<(String, Value)> {
+        fn walk(prefix: &str, value: Value, out: &mut Vec<(String, Value)>) {
+            match value {
+                Value::Object(map) => {
+                    for (key, value) in map {
+                        let key = if prefix.is_empty() {
+                            key
+                        } else {
+                            format!("{}.{}", prefix, key)
+                        };
+                        walk(&key, value, out);
+                    }
+                }
+                value => out.push((prefix.to_string(), value)),
+            }
+        }
+        let mut entries = Vec::new();
+        walk("", serde_json::to_value(self).unwrap(), &mut entries);
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn gets_dotted_keys() {
+        let config = Config::default();
+        assert_eq!(config.get("templates.comic_dir"), Some(json!("{title} - {author}")));
+        assert_eq!(config.get("timeouts.game.read"), Some(json!(60)));
+        assert_eq!(config.get("download"), Some(json!(false)));
+        assert_eq!(config.get("templates.nope"), None);
+        assert_eq!(config.get("password"), None);
+    }
+
+    #[test]
+    fn sets_json_values_and_falls_back_to_strings() {
+        let mut config = Config::default();
+        config.set("download", "true").unwrap();
+        config.set("jobs", "4").unwrap();
+        config.set("timeouts.api.read", "7").unwrap();
+        config.set("save_dir", "/srv/comics").unwrap();
+        config.set("templates.ep_dir", "{order} {ep}").unwrap();
+        assert!(config.download);
+        assert_eq!(config.jobs, Some(4));
+        assert_eq!(config.timeouts.api.read, Some(7));
+        assert_eq!(config.save_dir.as_deref(), Some("/srv/comics"));
+        assert_eq!(config.templates.ep_dir, "{order} {ep}");
+        config.set("jobs", "null").unwrap();
+        assert_eq!(config.jobs, None);
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_bad_values() {
+        let mut config = Config::default();
+        assert_eq!(config.set("downlaod", "true"), Err("unknown config key `downlaod`".to_string()));
+        assert_eq!(config.set("password", "secret"), Err("unknown config key `password`".to_string()));
+        assert!(config.set("jobs", "many").unwrap_err().starts_with("invalid value for `jobs`"));
+        assert_eq!(config.jobs, None);
+    }
+
+    #[test]
+    fn setting_a_profile_key_creates_the_profile() {
+        let mut config = Config::default();
+        config.set("profiles.work.user", "someone").unwrap();
+        config.set("profiles.work.save_dir", "/work").unwrap();
+        let account = &config.profiles["work"];
+        assert_eq!(account.user, "someone");
+        assert_eq!(account.save_dir.as_deref(), Some("/work"));
+        assert!(config.set("profiles.work.nope", "1").is_err());
+        assert!(config.set("profiles.other.nope", "1").is_err());
+        assert!(!config.profiles.contains_key("other"));
+
+        let profiled = config.with_profile(Some("work")).unwrap();
+        assert_eq!(profiled.user, "someone");
+        assert_eq!(profiled.save_dir.as_deref(), Some("/work"));
+        assert_eq!(
+            config.with_profile(Some("home")).unwrap_err(),
+            "unknown profile `home`"
+        );
+    }
+}
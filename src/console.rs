@@ -8,11 +8,21 @@ use libpicacg::{
 };
 use size_utils::Size;
 
+use crate::{blocklist::Blocklist, progress, queue::Entry};
+
 pub struct Console;
 
 impl Console {
+    /// Makes room on stderr for a message, taking the progress lines away
+    /// until the next progress report.
     pub fn clear_line() {
-        print!("\r{}[K", 27 as char);
+        progress::clear();
+    }
+
+    /// Errors go to stderr so they never mix with machine-readable output.
+    pub fn print_error(error: &Error) {
+        Self::clear_line();
+        eprintln!("{}", Self::format_error(error));
     }
 
     pub fn format_error(error: &Error) -> String {
@@ -152,4 +162,21 @@ impl Console {
             &value.name, &value.email, &value.exp, &value.level, &value.is_punched,
         )
     }
+
+    pub fn format_job(value: &Entry) -> String {
+        format!(
+            "Id[{}] Status[{}] Job[{}] SaveDir[{}]",
+            value.id,
+            value.status,
+            value.job,
+            value.job.save_dir()
+        )
+    }
+
+    pub fn format_blocklist(value: &Blocklist) -> String {
+        format!(
+            "Tags{:?} Authors{:?} Uploaders{:?} Cids{:?}",
+            &value.tags, &value.authors, &value.uploaders, &value.cids,
+        )
+    }
 }
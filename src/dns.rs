@@ -0,0 +1,162 @@
+use std::net::{IpAddr, SocketAddr};
+
+use clap::{ArgAction, Args};
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    Url,
+};
+use serde::{Deserialize, Serialize};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Parses `HOST:ADDR[,ADDR..]`, also accepting curl's `HOST:PORT:ADDR`.
+fn resolve_entry(value: &str) -> Result<(String, Vec<SocketAddr>), String> {
+    let invalid = || format!("`{}` is not HOST:ADDR[,ADDR..]", value);
+    let (host, rest) = value.split_once(':').ok_or_else(invalid)?;
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    let parse_addrs = |addrs: &str| {
+        addrs
+            .split(',')
+            .map(|addr| {
+                addr.trim()
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .parse::<IpAddr>()
+                    .map(|ip| SocketAddr::new(ip, 0))
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let addrs = parse_addrs(rest).or_else(|_| match rest.split_once(':') {
+        Some((port, addrs)) if port.parse::<u16>().is_ok() => {
+            parse_addrs(addrs).map_err(|err| format!("{}: {}", invalid(), err))
+        }
+        _ => Err(invalid()),
+    })?;
+    Ok((host.to_ascii_lowercase(), addrs))
+}
+
+pub fn parse_resolve(value: &str) -> Result<String, String> {
+    resolve_entry(value)?;
+    Ok(value.to_string())
+}
+
+pub fn parse_doh(value: &str) -> Result<String, String> {
+    let url = Url::parse(value).map_err(|err| format!("`{}` is not a URL: {}", value, err))?;
+    if url.scheme() != "https" {
+        return Err(format!("DNS-over-HTTPS resolver `{}` must be an https URL", value));
+    }
+    Ok(value.to_string())
+}
+
+/// Name resolution for API calls and downloads.
+#[derive(Args, Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct DnsOptions {
+    /// Pins a host to addresses instead of looking it up, as HOST:ADDR[,ADDR..]
+    #[clap(long = "resolve", action = ArgAction::Append, value_parser = parse_resolve)]
+    pub resolve: Vec<String>,
+    /// DNS-over-HTTPS endpoint speaking the JSON API, such as
+    /// https://1.1.1.1/dns-query, used for hosts not pinned with --resolve
+    #[clap(long = "doh", value_parser = parse_doh)]
+    pub doh: Option<String>,
+}
+
+impl DnsOptions {
+    pub fn or(mut self, defaults: &DnsOptions) -> Self {
+        if self.resolve.is_empty() {
+            self.resolve = defaults.resolve.clone();
+        }
+        self.doh = self.doh.or_else(|| defaults.doh.clone());
+        self
+    }
+
+    /// The pinned hosts with their addresses.
+    pub fn overrides(&self) -> Result<Vec<(String, Vec<SocketAddr>)>, String> {
+        self.resolve.iter().map(|entry| resolve_entry(entry)).collect()
+    }
+
+    /// The DNS-over-HTTPS endpoint, see [`crate::client::ClientBuilder::dns`].
+    pub fn doh_url(&self) -> Result<Option<Url>, String> {
+        self.doh
+            .as_deref()
+            .map(|url| {
+                parse_doh(url)?;
+                Ok(Url::parse(url).unwrap())
+            })
+            .transpose()
+    }
+}
+
+#[derive(Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    kind: u16,
+    data: String,
+}
+
+/// Looks hosts up through a DNS-over-HTTPS JSON endpoint.
+///
+/// Only the address changes, requests still carry the original host, so TLS
+/// SNI and certificate checks are unaffected.
+pub struct DohResolver {
+    url: Url,
+    client: reqwest::Client,
+}
+
+impl DohResolver {
+    /// Asks the endpoint at `url` through `client`, which must not resolve
+    /// through this resolver itself.
+    pub fn new(url: Url, client: reqwest::Client) -> Self {
+        Self { url, client }
+    }
+
+    async fn lookup(&self, host: &str, kind: u16) -> Result<Vec<SocketAddr>, BoxError> {
+        let body = self
+            .client
+            .get(self.url.clone())
+            .query(&[("name", host), ("type", &kind.to_string())])
+            .header("accept", "application/dns-json")
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let response: DohResponse = serde_json::from_slice(&body)?;
+        Ok(response
+            .answer
+            .into_iter()
+            .filter(|answer| answer.kind == kind)
+            .filter_map(|answer| answer.data.parse::<IpAddr>().ok())
+            .map(|ip| SocketAddr::new(ip, 0))
+            .collect())
+    }
+}
+
+impl Resolve for DohResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = DohResolver {
+            url: self.url.clone(),
+            client: self.client.clone(),
+        };
+        Box::pin(async move {
+            let host = name.as_str();
+            let mut addrs = resolver.lookup(host, 1).await?;
+            if addrs.is_empty() {
+                addrs = resolver.lookup(host, 28).await?;
+            }
+            if addrs.is_empty() {
+                return Err(format!("no address found for {}", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
@@ -0,0 +1,598 @@
+use std::{fmt, iter::Peekable, str::Chars, str::FromStr};
+
+use clap::{ArgAction, Args};
+use libpicacg::responses::{Comic, SearchRow};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Fields of a listed comic that filters can look at.
+pub trait Filterable {
+    fn id(&self) -> &str;
+    fn likes(&self) -> u64;
+    fn views(&self) -> u64;
+    fn finished(&self) -> bool;
+    fn author(&self) -> &str;
+    fn title(&self) -> &str;
+    /// Tags and categories.
+    fn tags(&self) -> Vec<&str>;
+}
+
+impl Filterable for Comic {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn likes(&self) -> u64 {
+        self.total_likes
+    }
+
+    fn views(&self) -> u64 {
+        self.total_views
+    }
+
+    fn finished(&self) -> bool {
+        self.finished
+    }
+
+    fn author(&self) -> &str {
+        &self.author
+    }
+
+    fn title(&self) -> &str {
+        &self.title
+    }
+
+    fn tags(&self) -> Vec<&str> {
+        self.categories
+            .iter()
+            .chain(self.tags.iter().flatten())
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+impl Filterable for SearchRow {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn likes(&self) -> u64 {
+        self.total_likes
+    }
+
+    fn views(&self) -> u64 {
+        self.total_views
+    }
+
+    fn finished(&self) -> bool {
+        self.finished
+    }
+
+    fn author(&self) -> &str {
+        &self.author
+    }
+
+    fn title(&self) -> &str {
+        &self.title
+    }
+
+    fn tags(&self) -> Vec<&str> {
+        self.categories
+            .iter()
+            .chain(self.tags.iter())
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// Client-side filters applied to listed comics before they are printed or downloaded.
+#[derive(Args, Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Filter {
+    #[clap(long = "min-likes")]
+    pub min_likes: Option<u64>,
+    #[clap(long = "min-views")]
+    pub min_views: Option<u64>,
+    #[clap(long = "finished-only", default_value = "false", action = ArgAction::SetTrue)]
+    pub finished_only: bool,
+    /// Lists unfinished comics too, even when the config sets `finished_only`
+    #[clap(long = "no-finished-only", default_value = "false", action = ArgAction::SetTrue, overrides_with = "finished_only")]
+    #[serde(skip)]
+    pub no_finished_only: bool,
+    #[clap(long = "author", action = ArgAction::Append)]
+    pub authors: Vec<String>,
+    #[clap(long = "exclude-author", action = ArgAction::Append)]
+    pub exclude_authors: Vec<String>,
+    #[clap(long = "exclude-tag", action = ArgAction::Append)]
+    pub exclude_tags: Vec<String>,
+    /// Predicate such as `likes > 500 && !tag("x")`.
+    #[clap(long = "filter")]
+    pub expr: Option<Expr>,
+}
+
+impl Filter {
+    pub fn matches<T: Filterable>(&self, value: &T) -> bool {
+        if self.min_likes.is_some_and(|min| value.likes() < min) {
+            return false;
+        }
+        if self.min_views.is_some_and(|min| value.views() < min) {
+            return false;
+        }
+        if self.finished_only && !value.finished() {
+            return false;
+        }
+        if !self.authors.is_empty() && !self.authors.iter().any(|a| a == value.author()) {
+            return false;
+        }
+        if self.exclude_authors.iter().any(|a| a == value.author()) {
+            return false;
+        }
+        let tags = value.tags();
+        if self.exclude_tags.iter().any(|t| tags.contains(&t.as_str())) {
+            return false;
+        }
+        self.expr.as_ref().is_none_or(|expr| expr.eval(value))
+    }
+
+    /// Fills every filter not given in `self` from `defaults`.
+    pub fn or(mut self, defaults: &Filter) -> Filter {
+        fn or_vec(values: &mut Vec<String>, defaults: &[String]) {
+            if values.is_empty() {
+                values.extend_from_slice(defaults);
+            }
+        }
+        self.min_likes = self.min_likes.or(defaults.min_likes);
+        self.min_views = self.min_views.or(defaults.min_views);
+        self.finished_only = !self.no_finished_only && (self.finished_only || defaults.finished_only);
+        or_vec(&mut self.authors, &defaults.authors);
+        or_vec(&mut self.exclude_authors, &defaults.exclude_authors);
+        or_vec(&mut self.exclude_tags, &defaults.exclude_tags);
+        self.expr = self.expr.or_else(|| defaults.expr.clone());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Likes,
+    Views,
+    Author,
+    Title,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Number(Field, Op, u64),
+    Text(Field, Op, String),
+    Tag(String),
+    Finished,
+}
+
+impl Expr {
+    pub fn eval<T: Filterable>(&self, value: &T) -> bool {
+        fn compare<V: PartialOrd + ?Sized>(left: &V, op: Op, right: &V) -> bool {
+            match op {
+                Op::Eq => left == right,
+                Op::Ne => left != right,
+                Op::Gt => left > right,
+                Op::Ge => left >= right,
+                Op::Lt => left < right,
+                Op::Le => left <= right,
+            }
+        }
+        match self {
+            Expr::And(left, right) => left.eval(value) && right.eval(value),
+            Expr::Or(left, right) => left.eval(value) || right.eval(value),
+            Expr::Not(expr) => !expr.eval(value),
+            Expr::Number(Field::Likes, op, n) => compare(&value.likes(), *op, n),
+            Expr::Number(_, op, n) => compare(&value.views(), *op, n),
+            Expr::Text(Field::Author, op, s) => compare(value.author(), *op, s.as_str()),
+            Expr::Text(_, op, s) => compare(value.title(), *op, s.as_str()),
+            Expr::Tag(tag) => value.tags().contains(&tag.as_str()),
+            Expr::Finished => value.finished(),
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Field::Likes => "likes",
+            Field::Views => "views",
+            Field::Author => "author",
+            Field::Title => "title",
+        })
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+        })
+    }
+}
+
+/// Writes the expression back in the syntax accepted by [`Expr::from_str`].
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn quote(s: &str) -> String {
+            let q = if s.contains('"') { '\'' } else { '"' };
+            format!("{}{}{}", q, s, q)
+        }
+        match self {
+            Expr::And(left, right) => write!(f, "({} && {})", left, right),
+            Expr::Or(left, right) => write!(f, "({} || {})", left, right),
+            Expr::Not(expr) => write!(f, "!{}", expr),
+            Expr::Number(field, op, n) => write!(f, "{} {} {}", field, op, n),
+            Expr::Text(field, op, s) => write!(f, "{} {} {}", field, op, quote(s)),
+            Expr::Tag(tag) => write!(f, "tag({})", quote(tag)),
+            Expr::Finished => f.write_str("finished"),
+        }
+    }
+}
+
+impl Serialize for Expr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Expr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Number(u64),
+    Text(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    fn next_is(chars: &mut Peekable<Chars>, c: char) -> bool {
+        chars.next_if_eq(&c).is_some()
+    }
+    let mut chars = s.chars().peekable();
+    let mut tokens = Vec::new();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' if next_is(&mut chars, '&') => Token::And,
+            '|' if next_is(&mut chars, '|') => Token::Or,
+            '!' if next_is(&mut chars, '=') => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '=' if next_is(&mut chars, '=') => Token::Op(Op::Eq),
+            '>' if next_is(&mut chars, '=') => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '<' if next_is(&mut chars, '=') => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '"' | '\'' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(ch) => text.push(ch),
+                        None => return Err(format!("unterminated string `{}{}`", c, text)),
+                    }
+                }
+                Token::Text(text)
+            }
+            c if c.is_ascii_digit() => {
+                let mut number = c.to_string();
+                while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                    number.push(d);
+                }
+                Token::Number(number.parse().map_err(|_| format!("`{}` is too large", number))?)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = c.to_string();
+                while let Some(d) = chars.next_if(|d| d.is_alphanumeric() || *d == '_') {
+                    ident.push(d);
+                }
+                Token::Ident(ident)
+            }
+            c => return Err(format!("unexpected `{}`", c)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over `or := and ("||" and)*`, `and := unary ("&&" unary)*`,
+/// `unary := "!" unary | "(" or ")" | predicate`.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            token => Err(format!("expected {:?}, found {:?}", expected, token)),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                self.expect(Token::Close)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(ident)) => self.predicate(&ident),
+            token => Err(format!("expected a predicate, found {:?}", token)),
+        }
+    }
+
+    fn predicate(&mut self, ident: &str) -> Result<Expr, String> {
+        let field = match ident {
+            "finished" => return Ok(Expr::Finished),
+            "tag" => {
+                self.expect(Token::Open)?;
+                let tag = match self.next() {
+                    Some(Token::Text(tag)) => tag,
+                    token => return Err(format!("tag() takes a string, found {:?}", token)),
+                };
+                self.expect(Token::Close)?;
+                return Ok(Expr::Tag(tag));
+            }
+            "likes" => Field::Likes,
+            "views" => Field::Views,
+            "author" => Field::Author,
+            "title" => Field::Title,
+            _ => return Err(format!("unknown field `{}`", ident)),
+        };
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            token => return Err(format!("expected a comparison after `{}`, found {:?}", ident, token)),
+        };
+        match (field, self.next()) {
+            (Field::Likes | Field::Views, Some(Token::Number(n))) => Ok(Expr::Number(field, op, n)),
+            (Field::Author | Field::Title, Some(Token::Text(s))) => Ok(Expr::Text(field, op, s)),
+            (_, token) => Err(format!("cannot compare `{}` with {:?}", ident, token)),
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Item {
+        likes: u64,
+        views: u64,
+        finished: bool,
+        author: &'static str,
+        title: &'static str,
+        tags: Vec<&'static str>,
+    }
+
+    impl Filterable for Item {
+        fn id(&self) -> &str {
+            "0123456789abcdef01234567"
+        }
+
+        fn likes(&self) -> u64 {
+            self.likes
+        }
+
+        fn views(&self) -> u64 {
+            self.views
+        }
+
+        fn finished(&self) -> bool {
+            self.finished
+        }
+
+        fn author(&self) -> &str {
+            self.author
+        }
+
+        fn title(&self) -> &str {
+            self.title
+        }
+
+        fn tags(&self) -> Vec<&str> {
+            self.tags.clone()
+        }
+    }
+
+    fn item() -> Item {
+        Item {
+            likes: 600,
+            views: 10000,
+            finished: false,
+            author: "anon",
+            title: "It's a title",
+            tags: vec!["romance", "school"],
+        }
+    }
+
+    fn parse(s: &str) -> Expr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let likes = || Box::new(Expr::Number(Field::Likes, Op::Gt, 1));
+        let views = || Box::new(Expr::Number(Field::Views, Op::Gt, 2));
+        let finished = || Box::new(Expr::Finished);
+        assert_eq!(
+            parse("likes > 1 || views > 2 && finished"),
+            Expr::Or(likes(), Box::new(Expr::And(views(), finished())))
+        );
+        assert_eq!(
+            parse("likes > 1 && views > 2 || finished"),
+            Expr::Or(Box::new(Expr::And(likes(), views())), finished())
+        );
+        assert_eq!(
+            parse("(likes > 1 || views > 2) && finished"),
+            Expr::And(Box::new(Expr::Or(likes(), views())), finished())
+        );
+    }
+
+    #[test]
+    fn not_applies_to_the_next_predicate() {
+        assert_eq!(
+            parse("!finished && likes >= 5"),
+            Expr::And(
+                Box::new(Expr::Not(Box::new(Expr::Finished))),
+                Box::new(Expr::Number(Field::Likes, Op::Ge, 5))
+            )
+        );
+        assert_eq!(parse("!!finished"), Expr::Not(Box::new(Expr::Not(Box::new(Expr::Finished)))));
+        assert_eq!(parse("likes != 5"), Expr::Number(Field::Likes, Op::Ne, 5));
+    }
+
+    #[test]
+    fn strings_take_either_quote() {
+        assert_eq!(parse(r#"tag("a b")"#), Expr::Tag("a b".to_string()));
+        assert_eq!(parse("tag('a \"b\"')"), Expr::Tag("a \"b\"".to_string()));
+        assert_eq!(
+            parse("title == \"It's a title\""),
+            Expr::Text(Field::Title, Op::Eq, "It's a title".to_string())
+        );
+    }
+
+    #[test]
+    fn errors_name_the_problem() {
+        let err = |s: &str| s.parse::<Expr>().unwrap_err();
+        assert_eq!(err("tag(\"x"), "unterminated string `\"x`");
+        assert_eq!(err("size > 1"), "unknown field `size`");
+        assert_eq!(err("likes > \"x\""), "cannot compare `likes` with Some(Text(\"x\"))");
+        assert_eq!(err("author == 1"), "cannot compare `author` with Some(Number(1))");
+        assert_eq!(err("likes 1"), "expected a comparison after `likes`, found Some(Number(1))");
+        assert_eq!(err("(finished"), "expected Close, found None");
+        assert_eq!(err("finished finished"), "unexpected Ident(\"finished\")");
+        assert_eq!(err("likes > 1 & views > 2"), "unexpected `&`");
+        assert_eq!(err(""), "expected a predicate, found None");
+        assert_eq!(err("likes > 99999999999999999999"), "`99999999999999999999` is too large");
+    }
+
+    #[test]
+    fn display_parses_back() {
+        for s in [
+            "likes > 500 && !tag(\"x\")",
+            "!(finished || views <= 10) && author != 'a \"b\"'",
+            "title == \"It's\" || likes < 1 && views >= 2",
+            "tag('y') || !!finished",
+        ] {
+            let expr = parse(s);
+            assert_eq!(parse(&expr.to_string()), expr, "{}", expr);
+        }
+    }
+
+    #[test]
+    fn eval_checks_every_predicate() {
+        let item = item();
+        assert!(parse("likes > 500 && views >= 10000").eval(&item));
+        assert!(!parse("finished").eval(&item));
+        assert!(parse("!finished && tag('school')").eval(&item));
+        assert!(!parse("tag('horror') || author == 'someone'").eval(&item));
+        assert!(parse("title == \"It's a title\"").eval(&item));
+    }
+
+    #[test]
+    fn filter_combines_options_and_expression() {
+        let filter = Filter {
+            min_likes: Some(100),
+            exclude_tags: vec!["horror".to_string()],
+            expr: Some(parse("views > 5000")),
+            ..Default::default()
+        };
+        assert!(filter.matches(&item()));
+        assert!(!filter.matches(&Item { likes: 50, ..item() }));
+        assert!(!filter.matches(&Item { tags: vec!["horror"], ..item() }));
+        assert!(!filter.matches(&Item { views: 10, ..item() }));
+    }
+
+    #[test]
+    fn command_line_overrides_configured_finished_only() {
+        let config = Filter {
+            finished_only: true,
+            ..Default::default()
+        };
+        assert!(Filter::default().or(&config).finished_only);
+        let filter = Filter {
+            no_finished_only: true,
+            ..Default::default()
+        };
+        assert!(!filter.or(&config).finished_only);
+    }
+}
@@ -0,0 +1,44 @@
+use std::{
+    fs::File,
+    io::{self, stdin, BufRead, BufReader},
+};
+
+/// Reads one id or link per line from `path`, or from stdin when `path` is `-`.
+///
+/// Blank lines and lines starting with `#` are skipped, every other line goes
+/// through `parse`.
+pub fn read_ids(path: &str, parse: fn(&str) -> Result<String, String>) -> io::Result<Vec<String>> {
+    let reader: Box<dyn BufRead> = if path == "-" {
+        Box::new(stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+    let mut ids = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let id = parse(line).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path, number + 1, err),
+            )
+        })?;
+        ids.push(id);
+    }
+    Ok(ids)
+}
+
+/// Appends the ids read from `input`, if any, to the ones given on the command line.
+pub fn merge_ids(
+    mut ids: Vec<String>,
+    input: Option<&str>,
+    parse: fn(&str) -> Result<String, String>,
+) -> io::Result<Vec<String>> {
+    if let Some(path) = input {
+        ids.extend(read_ids(path, parse)?);
+    }
+    Ok(ids)
+}
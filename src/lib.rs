@@ -1,3 +1,16 @@
+pub mod blocklist;
 pub mod client;
 pub mod command;
+pub mod config;
 pub mod console;
+pub mod dns;
+pub mod filter;
+pub mod input;
+pub mod output;
+pub mod pager;
+pub mod parse;
+pub mod progress;
+pub mod proxy;
+pub mod queue;
+pub mod rate;
+pub mod selector;
@@ -1,400 +1,376 @@
 #![allow(unused)]
 
 use clap::Parser;
-use client::Client;
 use configer::Configer;
-use console::Console;
 
-use picacg::command::{ComicOptions, GameOptions, GlobalOptions, SubCommand, UserOptions};
+use picacg::{
+    client::Client,
+    command::{
+        ComicOptions, GameOptions, GlobalOptions, QueueOptions, SortOption, SubCommand,
+        UserOptions,
+    },
+    config::{create_private, home_path, queue_path, remove_sessions, session_path, Config, Session, CONFIG_PATH},
+    console::Console,
+    input::merge_ids,
+    parse::{parse_comic_id, parse_game_id},
+    queue::DownloadQueue,
+    selector::EpSelector,
+};
 use reqwest::Proxy;
 use serde::{Deserialize, Serialize};
 use std::{
-    io::{stdin, stdout, Write},
+    io::{stderr, stdin, Write},
     time::Duration, env,
 };
 
-mod client;
-mod console;
-
 mod handle {
-    use crate::client::Client;
+    use futures::{FutureExt, StreamExt};
+    use libpicacg::Pagible;
+    use picacg::{
+        client::Client,
+        pager::{self, Paging},
+        queue::{DownloadQueue, Job},
+        retry,
+    };
     pub mod comic {
         use std::{path::PathBuf, str::FromStr};
 
-        use libpicacg::{error::Error, Sort};
-        use picacg::{command::GlobalOptions, console::Console};
+        use libpicacg::error::Error;
+        use picacg::{
+            command::{GlobalOptions, SortOption},
+            console::Console,
+            output::{Format, Printer},
+            selector::EpSelector,
+        };
 
         use super::*;
-        pub async fn ranking(client: &mut Client, options: &GlobalOptions) {
-            match client.comic_ranking().await {
+        pub async fn ranking(
+            client: &Client,
+            options: &GlobalOptions,
+            queue: &DownloadQueue,
+            limit: Option<usize>,
+        ) {
+            let mut printer = Printer::new(options.format());
+            match retry!(client, api.comic_ranking()) {
                 Ok(res) => {
-                    for comic in res.iter() {
-                        println!("{}", Console::format_comic(&comic));
+                    let comics = res
+                        .iter()
+                        .filter(|comic| options.filter.matches(*comic) && !client.blocklist().blocks(*comic))
+                        .take(limit.unwrap_or(usize::MAX));
+                    for comic in comics {
+                        printer.print(comic);
                         if options.download {
-                            while let Err(err) = client
-                                .comic_download_eps(&comic.id, &options.save_dir)
-                                .await
-                            {
-                                if let Error::Request(ref e) = err {
-                                    if !(e.is_timeout() || e.is_connect() || e.is_request()) {
-                                        break;
-                                    }
-                                }
-                                Console::clear_line();
-                                println!("{}", Console::format_error(&err));
-                            }
+                            queue.push(Job::Comic {
+                                cid: comic.id.clone(),
+                                eps: EpSelector::All,
+                                save_dir: options.save_dir().to_string(),
+                            });
                         }
                     }
                 }
                 Err(err) => {
-                    Console::clear_line();
-                    println!("{}", Console::format_error(&err))
+                    Console::print_error(&err);
                 }
             }
+            printer.finish();
         }
         pub async fn metadata(
-            client: &mut Client,
+            client: &Client,
             options: &GlobalOptions,
+            queue: &DownloadQueue,
             cids: Vec<String>,
             _save_dir: &str,
         ) {
-            let save_dir = PathBuf::from_str(&options.save_dir).unwrap();
+            let save_dir = PathBuf::from_str(options.save_dir()).unwrap();
+            let mut printer = Printer::new(options.format());
             for cid in cids {
-                match client.comic_metadata(&cid).await {
+                match retry!(client, api.comic_metadata(&cid)) {
                     Ok(res) => {
-                        println!("{}", Console::format_comic_metadata(&res));
+                        printer.print(&res);
                         if options.download {
-                            while let Err(err) = client
-                                .comic_download_eps(
-                                    &res.metadata.id,
-                                    save_dir.join(_save_dir).to_str().unwrap(),
-                                )
-                                .await
-                            {
-                                if let Error::Request(ref e) = err {
-                                    if !(e.is_timeout() || e.is_connect() || e.is_request()) {
-                                        break;
-                                    }
-                                }
-                                Console::clear_line();
-                                println!("{}", Console::format_error(&err));
-                            }
+                            queue.push(Job::Comic {
+                                cid: res.metadata.id.clone(),
+                                eps: EpSelector::All,
+                                save_dir: save_dir.join(_save_dir).to_str().unwrap().to_string(),
+                            });
                         }
                     }
                     Err(err) => {
-                        Console::clear_line();
-                        println!("{}", Console::format_error(&err))
+                        Console::print_error(&err);
                     }
                 }
             }
+            printer.finish();
         }
         pub async fn recommended(
-            client: &mut Client,
+            client: &Client,
             options: &GlobalOptions,
+            queue: &DownloadQueue,
             cids: Vec<String>,
+            limit: Option<usize>,
             _save_dir: &str,
         ) {
-            let save_dir = PathBuf::from_str(&options.save_dir).unwrap();
+            let save_dir = PathBuf::from_str(options.save_dir()).unwrap();
+            let mut printer = Printer::new(options.format());
+            let mut left = limit.unwrap_or(usize::MAX);
             for cid in cids {
-                match client.comic_recommended(&cid).await {
+                if left == 0 {
+                    break;
+                }
+                match retry!(client, api.comic_recommended(&cid)) {
                     Ok(res) => {
-                        for comic in res.iter() {
-                            println!("{}", Console::format_comic(&comic));
+                        let comics = res
+                            .iter()
+                            .filter(|comic| options.filter.matches(*comic) && !client.blocklist().blocks(*comic))
+                            .take(left);
+                        for comic in comics {
+                            left -= 1;
+                            printer.print(comic);
                             if options.download {
-                                while let Err(err) = client
-                                    .comic_download_eps(
-                                        &comic.id,
-                                        save_dir.join(_save_dir).to_str().unwrap(),
-                                    )
-                                    .await
-                                {
-                                    if let Error::Request(ref e) = err {
-                                        if !(e.is_timeout() || e.is_connect() || e.is_request()) {
-                                            break;
-                                        }
-                                    }
-                                    Console::clear_line();
-                                    println!("{}", Console::format_error(&err));
-                                }
+                                queue.push(Job::Comic {
+                                    cid: comic.id.clone(),
+                                    eps: EpSelector::All,
+                                    save_dir: save_dir.join(_save_dir).to_str().unwrap().to_string(),
+                                });
                             }
                         }
                     }
                     Err(err) => {
-                        Console::clear_line();
-                        println!("{}", Console::format_error(&err))
+                        Console::print_error(&err);
                     }
                 }
             }
+            printer.finish();
         }
         pub async fn eps(
-            client: &mut Client,
+            client: &Client,
             options: &GlobalOptions,
+            queue: &DownloadQueue,
             cid: String,
-            start: u64,
-            end: Option<u64>,
+            paging: Paging,
             _save_dir: &str,
         ) {
-            let save_dir = PathBuf::from_str(&options.save_dir).unwrap();
-            let mut end = end.unwrap_or(start);
-            let mut page = start;
-            loop {
-                match client.comic_eps(&cid, page).await {
-                    Ok(res) => {
-                        if options.until_end {
-                            end = res.pages;
-                        }
-                        for ep in res.iter() {
-                            println!("{}", Console::format_ep(ep));
-                        }
-                        if options.download {
-                            while let Err(err) = client
-                                .comic_download_eps(
-                                    &cid,
-                                    save_dir.join(_save_dir).to_str().unwrap(),
-                                )
-                                .await
-                            {
-                                if let Error::Request(ref e) = err {
-                                    if !(e.is_timeout() || e.is_connect() || e.is_request()) {
-                                        break;
-                                    }
-                                }
-                                Console::clear_line();
-                                println!("{}", Console::format_error(&err));
-                            }
-                        }
-                    }
+            let save_dir = PathBuf::from_str(options.save_dir()).unwrap();
+            let mut printer = Printer::new(options.format());
+            let cid = cid.as_str();
+            let mut eps = pager::items(
+                options.page_range(&paging),
+                move |page| async move { retry!(client, api.comic_eps(cid, page)) }.boxed(),
+                |res| res.to_vec(),
+            )
+            .limit(paging.limit);
+            while let Some(ep) = eps.next().await {
+                let ep = match ep {
+                    Ok(ep) => ep,
                     Err(err) => {
-                        Console::clear_line();
-                        println!("{}", Console::format_error(&err))
+                        Console::print_error(&err);
+                        break;
                     }
-                }
-                if page < end {
-                    page += 1;
-                } else {
-                    break;
+                };
+                printer.print(&ep);
+                let Some(order) = ep.order else {
+                    continue;
+                };
+                if options.download {
+                    queue.push(Job::Episode {
+                        cid: cid.to_string(),
+                        order,
+                        save_dir: save_dir.join(_save_dir).to_str().unwrap().to_string(),
+                    });
                 }
             }
+            printer.finish();
         }
+        #[allow(clippy::too_many_arguments)]
         pub async fn pages(
-            client: &mut Client,
+            client: &Client,
             options: &GlobalOptions,
+            queue: &DownloadQueue,
             cid: String,
             start_index: u64,
-            _end_index: Option<u64>,
-            start: u64,
-            end: Option<u64>,
+            end_index: Option<u64>,
+            paging: Paging,
             _save_dir: &str,
         ) {
-            let save_dir = PathBuf::from_str(&options.save_dir).unwrap();
-            let mut end_index = end.unwrap_or(start_index);
+            if options.download && paging.limit.is_some() {
+                eprintln!("--limit cannot be used with --download here, downloads go by whole API pages");
+                return;
+            }
+            let save_dir = PathBuf::from_str(options.save_dir()).unwrap();
+            let mut printer = Printer::new(options.format());
+            let cid = cid.as_str();
+            let end_index = end_index.unwrap_or(start_index);
             for page_index in start_index..=end_index {
-                let mut end = end.unwrap_or(start);
-                let mut page = start;
-                loop {
-                    match client.comic_pages(&cid, page_index, page).await {
-                        Ok(res) => {
-                            if options.until_end {
-                                end = res.pages;
-                            }
-                            for page in res.iter() {
-                                println!("{}", Console::format_page(page));
-                            }
-                            if options.download {
-                                while let Err(err) = client
-                                    .comic_download_eps(
-                                        &cid,
-                                        save_dir.join(_save_dir).to_str().unwrap(),
-                                    )
-                                    .await
-                                {
-                                    if let Error::Request(ref e) = err {
-                                        if !(e.is_timeout() || e.is_connect() || e.is_request()) {
-                                            break;
-                                        }
-                                    }
-                                    Console::clear_line();
-                                    println!("{}", Console::format_error(&err));
-                                }
-                            }
-                        }
+                let mut pictures = pager::items(
+                    options.page_range(&paging),
+                    move |page| {
+                        async move { retry!(client, api.comic_pages(cid, page_index, page)) }.boxed()
+                    },
+                    |res| res.iter().map(|picture| (res.current(), picture.clone())).collect(),
+                )
+                .limit(paging.limit);
+                let mut downloaded = None;
+                while let Some(picture) = pictures.next().await {
+                    let (page, picture) = match picture {
+                        Ok(picture) => picture,
                         Err(err) => {
-                            Console::clear_line();
-                            println!("{}", Console::format_error(&err))
+                            Console::print_error(&err);
+                            break;
                         }
-                    }
-                    if page < end {
-                        page += 1;
-                    } else {
-                        break;
+                    };
+                    printer.print(&picture);
+                    if options.download && downloaded != Some(page) {
+                        downloaded = Some(page);
+                        queue.push(Job::Pages {
+                            cid: cid.to_string(),
+                            order: page_index,
+                            page,
+                            save_dir: save_dir.join(_save_dir).to_str().unwrap().to_string(),
+                        });
                     }
                 }
             }
+            printer.finish();
         }
         pub async fn pic_like_get(
-            client: &mut Client,
+            client: &Client,
             options: &GlobalOptions,
+            queue: &DownloadQueue,
             cid: String,
-            start: u64,
-            end: Option<u64>,
+            paging: Paging,
             _save_dir: &str,
         ) {
-            let save_dir = PathBuf::from_str(&options.save_dir).unwrap();
-            let mut end = end.unwrap_or(start);
-            let mut page = start;
-            loop {
-                match client.pic_like_get(&cid, page).await {
-                    Ok(res) => {
-                        for comic in res.iter() {
-                            println!("{}", Console::format_recommend_pic_like(comic));
-                        }
-                        if options.download {
-                            while let Err(err) = client
-                                .comic_download_eps(
-                                    &cid,
-                                    save_dir.join(_save_dir).to_str().unwrap(),
-                                )
-                                .await
-                            {
-                                if let Error::Request(ref e) = err {
-                                    if !(e.is_timeout() || e.is_connect() || e.is_request()) {
-                                        break;
-                                    }
-                                }
-                                Console::clear_line();
-                                println!("{}", Console::format_error(&err));
-                            }
-                        }
-                    }
+            let save_dir = PathBuf::from_str(options.save_dir()).unwrap();
+            let mut printer = Printer::new(options.format());
+            let cid = cid.as_str();
+            let mut comics = pager::items(
+                options.page_range(&paging),
+                move |page| async move { retry!(client, api.pic_like_get(cid, page)) }.boxed(),
+                |res| res.to_vec(),
+            )
+            .limit(paging.limit);
+            while let Some(comic) = comics.next().await {
+                let comic = match comic {
+                    Ok(comic) => comic,
                     Err(err) => {
-                        Console::clear_line();
-                        println!("{}", Console::format_error(&err))
+                        Console::print_error(&err);
+                        break;
                     }
-                }
-                if page < end {
-                    page += 1;
-                } else {
-                    break;
+                };
+                printer.print(&comic);
+                if options.download {
+                    queue.push(Job::Comic {
+                        cid: comic.id.clone(),
+                        eps: EpSelector::All,
+                        save_dir: save_dir.join(_save_dir).to_str().unwrap().to_string(),
+                    });
                 }
             }
+            printer.finish();
         }
+        #[allow(clippy::too_many_arguments)]
         pub async fn search(
-            client: &mut Client,
+            client: &Client,
             options: &GlobalOptions,
+            queue: &DownloadQueue,
             keyword: String,
-            start: u64,
-            end: Option<u64>,
+            sort: SortOption,
+            categories: Vec<String>,
+            paging: Paging,
             _save_dir: &str,
         ) {
-            let save_dir = PathBuf::from_str(&options.save_dir).unwrap();
-            let mut end = end.unwrap_or(start);
-            let mut page = start;
-            loop {
-                match client.search(&keyword, page, Sort::MaxLike).await {
-                    Ok(res) => {
-                        if options.until_end {
-                            end = res.pages;
-                        }
-                        for row in res.iter() {
-                            println!("{}", Console::format_searchrow(row));
-                            if options.download {
-                                while let Err(err) = client
-                                    .comic_download_eps(
-                                        &row.id,
-                                        save_dir.join(_save_dir).to_str().unwrap(),
-                                    )
-                                    .await
-                                {
-                                    if let Error::Request(ref e) = err {
-                                        if !(e.is_timeout() || e.is_connect() || e.is_request()) {
-                                            break;
-                                        }
-                                    }
-                                    Console::clear_line();
-                                    println!("{}", Console::format_error(&err));
-                                }
-                            }
-                        }
-                    }
+            let save_dir = PathBuf::from_str(options.save_dir()).unwrap();
+            let mut printer = Printer::new(options.format());
+            let keyword = keyword.as_str();
+            let mut rows = pager::items(
+                options.page_range(&paging),
+                move |page| async move { retry!(client, api.search(keyword, page, sort.into())) }.boxed(),
+                |res| {
+                    res.iter()
+                        .filter(|row| {
+                            categories.is_empty() || row.categories.iter().any(|c| categories.contains(c))
+                        })
+                        .filter(|row| options.filter.matches(*row) && !client.blocklist().blocks(*row))
+                        .cloned()
+                        .collect()
+                },
+            )
+            .limit(paging.limit);
+            while let Some(row) = rows.next().await {
+                let row = match row {
+                    Ok(row) => row,
                     Err(err) => {
-                        Console::clear_line();
-                        println!("{}", Console::format_error(&err))
+                        Console::print_error(&err);
+                        break;
                     }
-                }
-                if page < end {
-                    page += 1;
-                } else {
-                    break;
+                };
+                printer.print(&row);
+                if options.download {
+                    queue.push(Job::Comic {
+                        cid: row.id.clone(),
+                        eps: EpSelector::All,
+                        save_dir: save_dir.join(_save_dir).to_str().unwrap().to_string(),
+                    });
                 }
             }
+            printer.finish();
         }
         pub async fn favourites(
-            client: &mut Client,
+            client: &Client,
             options: &GlobalOptions,
-            start: u64,
-            end: Option<u64>,
+            queue: &DownloadQueue,
+            sort: SortOption,
+            paging: Paging,
             _save_dir: &str,
         ) {
-            let save_dir = PathBuf::from_str(&options.save_dir).unwrap();
-            let mut end = end.unwrap_or(start);
-            let mut page = start;
-            loop {
-                match client.favorites(page, Sort::DescByDate).await {
-                    Ok(res) => {
-                        if options.until_end {
-                            end = res.pages;
-                        }
-                        for comic in res.iter() {
-                            println!("{}", Console::format_comic(comic));
-                            if options.download {
-                                while let Err(err) = client
-                                    .comic_download_eps(
-                                        &comic.id,
-                                        save_dir.join(_save_dir).to_str().unwrap(),
-                                    )
-                                    .await
-                                {
-                                    if let Error::Request(ref e) = err {
-                                        if !(e.is_timeout() || e.is_connect() || e.is_request()) {
-                                            break;
-                                        }
-                                    }
-                                    Console::clear_line();
-                                    println!("{}", Console::format_error(&err));
-                                }
-                            }
-                        }
-                    }
+            let save_dir = PathBuf::from_str(options.save_dir()).unwrap();
+            let mut printer = Printer::new(options.format());
+            let mut comics = pager::items(
+                options.page_range(&paging),
+                move |page| async move { retry!(client, api.favorites(page, sort.into())) }.boxed(),
+                |res| {
+                    res.iter()
+                        .filter(|comic| options.filter.matches(*comic) && !client.blocklist().blocks(*comic))
+                        .cloned()
+                        .collect()
+                },
+            )
+            .limit(paging.limit);
+            while let Some(comic) = comics.next().await {
+                let comic = match comic {
+                    Ok(comic) => comic,
                     Err(err) => {
-                        Console::clear_line();
-                        println!("{}", Console::format_error(&err))
+                        Console::print_error(&err);
+                        break;
                     }
-                }
-                if page < end {
-                    page += 1;
-                } else {
-                    break;
+                };
+                printer.print(&comic);
+                if options.download {
+                    queue.push(Job::Comic {
+                        cid: comic.id.clone(),
+                        eps: EpSelector::All,
+                        save_dir: save_dir.join(_save_dir).to_str().unwrap().to_string(),
+                    });
                 }
             }
+            printer.finish();
         }
         pub async fn download(
-            client: &mut Client,
+            client: &Client,
             options: &GlobalOptions,
+            queue: &DownloadQueue,
             cids: Vec<String>,
+            selector: EpSelector,
             _save_dir: &str,
         ) {
-            let save_dir = PathBuf::from_str(&options.save_dir).unwrap();
-
-            for cid in &cids {
-                match client
-                    .comic_download_eps(cid, save_dir.join(_save_dir).to_str().unwrap())
-                    .await
-                {
-                    Ok(()) => {}
-                    Err(err) => {
-                        println!("{}", Console::format_error(&err))
-                    }
-                }
+            let save_dir = PathBuf::from_str(options.save_dir()).unwrap();
+
+            for cid in cids {
+                queue.push(Job::Comic {
+                    cid,
+                    eps: selector.clone(),
+                    save_dir: save_dir.join(_save_dir).to_str().unwrap().to_string(),
+                });
             }
         }
     }
@@ -402,143 +378,300 @@ mod handle {
         use std::{path::PathBuf, str::FromStr};
 
         use libpicacg::error::Error;
-        use picacg::{command::GlobalOptions, console::Console};
+        use picacg::{
+            command::GlobalOptions,
+            console::Console,
+            output::{Format, Printer},
+        };
 
         use super::*;
 
         pub async fn games(
-            client: &mut Client,
+            client: &Client,
             options: &GlobalOptions,
-            start: u64,
-            end: Option<u64>,
+            queue: &DownloadQueue,
+            paging: Paging,
             _save_dir: &str,
         ) {
-            let save_dir = PathBuf::from_str(&options.save_dir).unwrap();
-            let mut end = end.unwrap_or(start);
-            let mut page = start;
-            loop {
-                match client.games(page).await {
-                    Ok(res) => {
-                        if options.until_end {
-                            end = res.pages;
-                        }
-                        for game in res.iter() {
-                            println!("{}", Console::format_game(game));
-                            if options.download {
-                                while let Err(err) = client
-                                    .game_download(
-                                        &game.id,
-                                        save_dir.join(_save_dir).to_str().unwrap(),
-                                    )
-                                    .await
-                                {
-                                    if let Error::Request(ref e) = err {
-                                        if !(e.is_timeout() || e.is_connect() || e.is_request()) {
-                                            break;
-                                        }
-                                    }
-                                    Console::clear_line();
-                                    println!("{}", Console::format_error(&err));
-                                }
-                            }
-                        }
-                    }
+            let save_dir = PathBuf::from_str(options.save_dir()).unwrap();
+            let mut printer = Printer::new(options.format());
+            let mut games = pager::items(
+                options.page_range(&paging),
+                move |page| async move { retry!(client, api.games(page)) }.boxed(),
+                |res| res.to_vec(),
+            )
+            .limit(paging.limit);
+            while let Some(game) = games.next().await {
+                let game = match game {
+                    Ok(game) => game,
                     Err(err) => {
-                        Console::clear_line();
-                        println!("{}", Console::format_error(&err))
+                        Console::print_error(&err);
+                        break;
                     }
-                }
-                if page < end {
-                    page += 1;
-                } else {
-                    break;
+                };
+                printer.print(&game);
+                if options.download {
+                    queue.push(Job::Game {
+                        cid: game.id.clone(),
+                        save_dir: save_dir.join(_save_dir).to_str().unwrap().to_string(),
+                    });
                 }
             }
+            printer.finish();
         }
         pub async fn info(
-            client: &mut Client,
+            client: &Client,
             options: &GlobalOptions,
+            queue: &DownloadQueue,
             cids: Vec<String>,
             description: bool,
             _save_dir: &str,
         ) {
-            let save_dir = PathBuf::from_str(&options.save_dir).unwrap();
+            let save_dir = PathBuf::from_str(options.save_dir()).unwrap();
+            let mut printer = Printer::new(options.format());
             for cid in cids {
-                match client.game_info(&cid).await {
+                match retry!(client, api.game_info(&cid)) {
                     Ok(res) => {
-                        println!("{}", Console::format_game_info(&res));
-                        if description {
-                            println!("{}", res.description.as_ref().map(|s| s.as_str()).unwrap_or(""));
+                        printer.print(&res);
+                        if description && options.format() == Format::Text {
+                            println!("{}", res.description.as_deref().unwrap_or(""));
                         }
                         if options.download {
-                            while let Err(err) = client
-                                .game_download(&res.id, save_dir.join(_save_dir).to_str().unwrap())
-                                .await
-                            {
-                                if let Error::Request(ref e) = err {
-                                    if e.is_timeout() || e.is_connect() || e.is_request() {
-                                        continue;
-                                    } else {
-                                        break;
-                                    }
-                                }
-                                Console::clear_line();
-                                println!("{}", Console::format_error(&err));
-                            }
+                            queue.push(Job::Game {
+                                cid: res.id.clone(),
+                                save_dir: save_dir.join(_save_dir).to_str().unwrap().to_string(),
+                            });
                         }
                     }
                     Err(err) => {
-                        Console::clear_line();
-                        println!("{}", Console::format_error(&err))
+                        Console::print_error(&err);
                     }
                 }
             }
+            printer.finish();
         }
         pub async fn download(
-            client: &mut Client,
+            client: &Client,
             options: &GlobalOptions,
+            queue: &DownloadQueue,
             cids: Vec<String>,
             _save_dir: &str,
         ) {
-            let save_dir = PathBuf::from_str(&options.save_dir).unwrap();
+            let save_dir = PathBuf::from_str(options.save_dir()).unwrap();
 
             for cid in cids {
-                match client
-                    .game_download(&cid, save_dir.join(_save_dir).to_str().unwrap())
-                    .await
-                {
-                    Ok(_res) => {}
-                    Err(err) => {
-                        println!("{}", Console::format_error(&err))
-                    }
-                }
+                queue.push(Job::Game {
+                    cid,
+                    save_dir: save_dir.join(_save_dir).to_str().unwrap().to_string(),
+                });
             }
         }
     }
     pub mod user {
-        use picacg::console::Console;
+        use configer::Configer;
+        use picacg::{
+            command::GlobalOptions,
+            config::{remove_sessions, session_path, Config, CONFIG_PATH},
+            console::Console,
+            output::Printer,
+        };
 
         use super::*;
 
-        pub async fn punch_in(client: &mut Client) {
-            match client.punch_in().await {
+        pub fn logout(configer: &Configer, config: &mut Config) {
+            if let Err(err) = remove_sessions() {
+                eprintln!("Failed to remove the cached sessions: {}", err);
+            }
+            config.password.clear();
+            configer.write(CONFIG_PATH, &config);
+        }
+
+        pub async fn punch_in(client: &Client, options: &GlobalOptions) {
+            let mut printer = Printer::new(options.format());
+            match retry!(client, api.punch_in()) {
                 Ok(res) => {
-                    println!("{}", Console::format_punch_in(&res));
+                    printer.print(&res);
                 }
                 Err(err) => {
-                    Console::clear_line();
-                    println!("{}", Console::format_error(&err))
+                    Console::print_error(&err);
                 }
             }
+            printer.finish();
         }
-        pub async fn profile(client: &mut Client) {
-            match client.profile().await {
+        /// Punches in the default account, when it has a user, and every profile.
+        pub async fn punch_in_all(configer: &Configer, config: &Config, cli_options: &GlobalOptions) {
+            let mut profiles: Vec<Option<&str>> = Vec::new();
+            if !config.user.is_empty() {
+                profiles.push(None);
+            }
+            profiles.extend(config.profiles.keys().map(|name| Some(name.as_str())));
+            if cli_options.password_stdin {
+                eprintln!("--password-stdin cannot be used with --all-profiles");
+                return;
+            }
+            let mut printer = Printer::new(cli_options.format.unwrap_or_default());
+            for profile in profiles {
+                let label = profile.unwrap_or("default");
+                let mut account = config.with_profile(profile).unwrap();
+                if account.user.is_empty() {
+                    eprintln!("Skipping profile {}: no user configured", label);
+                    continue;
+                }
+                let mut options = cli_options.clone();
+                options.apply_config(&account);
+                let session_path = session_path(profile);
+                let Some(client) =
+                    crate::connect(configer, &options, &mut account, &session_path, false).await
+                else {
+                    continue;
+                };
+                eprintln!("{} ({}):", label, account.user);
+                match retry!(client, api.punch_in()) {
+                    Ok(res) => {
+                        printer.print(&res);
+                    }
+                    Err(err) => {
+                        Console::print_error(&err);
+                    }
+                }
+                crate::write_session(configer, &client, &session_path, false);
+            }
+            printer.finish();
+        }
+        pub async fn profile(client: &Client, options: &GlobalOptions) {
+            let mut printer = Printer::new(options.format());
+            match retry!(client, api.profile()) {
                 Ok(res) => {
-                    println!("{}", Console::format_profile(&res));
+                    printer.print(&res);
+                }
+                Err(err) => {
+                    Console::print_error(&err);
+                }
+            }
+            printer.finish();
+        }
+    }
+    pub mod config {
+        use std::{env, process::Command};
+
+        use configer::Configer;
+        use picacg::{
+            command::ConfigOptions,
+            config::{home_path, Config, CONFIG_PATH},
+        };
+        use serde_json::Value;
+
+        fn format_value(value: &Value) -> String {
+            match value {
+                Value::String(s) => s.clone(),
+                value => value.to_string(),
+            }
+        }
+
+        pub fn config(configer: &Configer, config: &mut Config, opts: ConfigOptions) {
+            match opts {
+                ConfigOptions::Get { key } => match config.get(&key) {
+                    Some(value) => println!("{}", format_value(&value)),
+                    None => eprintln!("unknown config key `{}`", key),
+                },
+                ConfigOptions::Set { key, value } => match config.set(&key, &value) {
+                    Ok(()) => {
+                        configer.write(CONFIG_PATH, &config);
+                    }
+                    Err(err) => eprintln!("{}", err),
+                },
+                ConfigOptions::List => {
+                    for (key, value) in config.entries() {
+                        println!("{} = {}", key, format_value(&value));
+                    }
+                }
+                ConfigOptions::Edit => {
+                    configer.write(CONFIG_PATH, &config);
+                    let path = home_path(CONFIG_PATH);
+                    let editor = env::var("VISUAL")
+                        .or_else(|_| env::var("EDITOR"))
+                        .unwrap_or_else(|_| "vi".to_string());
+                    match Command::new(&editor).arg(&path).status() {
+                        Ok(_) => {
+                            if let Some(Err(err)) = configer.read::<Config>(CONFIG_PATH) {
+                                eprintln!("Invalid config ~/{}: {:?}", CONFIG_PATH, err);
+                            }
+                        }
+                        Err(err) => eprintln!("Failed to run {}: {}", editor, err),
+                    }
+                }
+            }
+        }
+    }
+    pub mod queue {
+        use picacg::{
+            command::GlobalOptions,
+            config::home_path,
+            output::Printer,
+            queue::{read_journal, remove_finished, write_journal, DownloadQueue, JournalLock},
+        };
+
+        pub fn list(options: &GlobalOptions, queue_path: &str) {
+            let entries = match read_journal(&home_path(queue_path)) {
+                Ok(entries) => entries,
+                Err(err) => {
+                    eprintln!("Invalid queue ~/{}: {}", queue_path, err);
+                    return;
+                }
+            };
+            let mut printer = Printer::new(options.format());
+            for entry in &entries {
+                printer.print(entry);
+            }
+            printer.finish();
+        }
+
+        pub fn clear(all: bool, queue_path: &str) {
+            let path = home_path(queue_path);
+            let _lock = match JournalLock::acquire(&path) {
+                Ok(lock) => lock,
+                Err(err) => {
+                    eprintln!("Queue ~/{} is locked: {}", queue_path, err);
+                    return;
                 }
+            };
+            let mut entries = match read_journal(&path) {
+                Ok(entries) => entries,
                 Err(err) => {
-                    Console::clear_line();
-                    println!("{}", Console::format_error(&err))
+                    eprintln!("Invalid queue ~/{}: {}", queue_path, err);
+                    return;
+                }
+            };
+            let removed = remove_finished(&mut entries, all);
+            match write_journal(&path, &entries) {
+                Ok(()) => eprintln!("Removed {} jobs", removed),
+                Err(err) => eprintln!("Failed to save the queue to ~/{}: {}", queue_path, err),
+            }
+        }
+
+        pub fn resume(queue: &DownloadQueue) {
+            eprintln!("Resuming {} jobs", queue.resume());
+        }
+
+        pub fn retry_failed(queue: &DownloadQueue) {
+            eprintln!("Retrying {} jobs", queue.retry_failed());
+        }
+    }
+    pub mod blocklist {
+        use picacg::{
+            blocklist::Blocklist,
+            command::{BlocklistOptions, GlobalOptions},
+            output::Printer,
+        };
+
+        pub fn blocklist(blocklist: &mut Blocklist, options: &GlobalOptions, opts: BlocklistOptions) {
+            match opts {
+                BlocklistOptions::Add(entries) => blocklist.add(&entries),
+                BlocklistOptions::Remove(entries) => blocklist.remove(&entries),
+                BlocklistOptions::List => {
+                    let mut printer = Printer::new(options.format());
+                    printer.print(blocklist);
+                    printer.finish();
                 }
             }
         }
@@ -546,152 +679,444 @@ mod handle {
 }
 
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Config {
-    user: String,
-    password: String,
+/// Environment variable holding the password used to log in.
+const PASSWORD_ENV: &str = "PICACG_PASSWORD";
+/// Environment variable holding a session token used instead of logging in.
+const TOKEN_ENV: &str = "PICACG_TOKEN";
+
+fn env_value(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
 }
 
-fn main() {
-    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
-    runtime.block_on(async {
-        let options = GlobalOptions::parse();
-        let mut client = Client::new();
-        if let Some(ref v) = options.all_proxy {
-            client.set_proxy(Some(Proxy::all(v).unwrap())).unwrap();
-        } else if let Some(ref v) = options.https_proxy {
-            client.set_proxy(Some(Proxy::https(v).unwrap())).unwrap();
-        } else if let Some(ref v) = options.http_proxy {
-            client.set_proxy(Some(Proxy::http(v).unwrap())).unwrap();
-        }
+/// Reads one line from stdin without its line ending, `None` at EOF.
+fn read_line(prompt: Option<&str>) -> Option<String> {
+    if let Some(prompt) = prompt {
+        eprint!("{}", prompt);
+        stderr().flush().unwrap();
+    }
+    let mut line = String::new();
+    match stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_owned()),
+    }
+}
 
-        let configer = Configer::new(&env::var("HOME").unwrap());
+fn prompt_password(user: &str) -> Option<String> {
+    rpassword::prompt_password(format!("Password for {}: ", user)).ok()
+}
 
+/// Caches the session token, except one handed over through `PICACG_TOKEN`
+/// when `use_env` is set.
+fn write_session(configer: &Configer, client: &Client, path: &str, use_env: bool) {
+    if use_env && env_value(TOKEN_ENV).is_some() {
+        return;
+    }
+    if let Some(session) = client.session() {
+        if let Err(err) = create_private(&home_path(path)) {
+            eprintln!("Failed to create ~/{} readable by you only: {}", path, err);
+            return;
+        }
+        configer.write(path, &session);
+    }
+}
 
-        client.set_timeout(Some(Duration::from_secs(5))).unwrap();
+/// Builds a client for the account in `config`, using `PICACG_TOKEN`, the session
+/// cached at `session_path` or a fresh login. The user and password are prompted
+/// for when unknown, unless `--no-interactive` is given.
+///
+/// `PICACG_TOKEN`, `PICACG_PASSWORD` and `--password-stdin` name a single
+/// account, so they are only looked at with `use_env`.
+async fn connect(
+    configer: &Configer,
+    options: &GlobalOptions,
+    config: &mut Config,
+    session_path: &str,
+    use_env: bool,
+) -> Option<Client> {
+    let (api_proxy, cdn_proxy) = match (options.proxy.api(), options.proxy.cdn()) {
+        (Ok(api), Ok(cdn)) => (api, cdn),
+        (Err(err), _) | (_, Err(err)) => {
+            eprintln!("Invalid proxy: {}", err);
+            return None;
+        }
+    };
+    let (resolve, doh) = match (options.dns.overrides(), options.dns.doh_url()) {
+        (Ok(resolve), Ok(doh)) => (resolve, doh),
+        (Err(err), _) | (_, Err(err)) => {
+            eprintln!("Invalid DNS settings: {}", err);
+            return None;
+        }
+    };
+    let client = Client::builder()
+        .api_proxy(api_proxy)
+        .cdn_proxy(cdn_proxy)
+        .dns(resolve, doh)
+        .download_timeouts(options.timeouts)
+        .concurrency(options.concurrency)
+        .rate_limit(options.rate_limit, options.rate_jitter())
+        .bandwidth(options.bandwidth)
+        .retry_policy(options.retry)
+        .image_quality(options.image_quality.unwrap_or_default())
+        .image_server(options.image_server.clone())
+        .templates(config.templates.clone())
+        .blocklist(config.blocklist.clone())
+        .password_prompt(if options.no_interactive { None } else { Some(prompt_password) })
+        .build();
+    let client = match client {
+        Ok(client) => client,
+        Err(err) => {
+            Console::print_error(&err);
+            return None;
+        }
+    };
 
-        let config: Config = if let Some(Ok(cfg)) = configer.read(".config/picacg/config") {
-            cfg
-        } else {
-            let mut user = String::new();
-            let mut passwd = String::new();
-
-            let std_in = stdin();
-            let mut std_out = stdout();
-            print!("User: ");
-            std_out.flush().unwrap();
-            std_in.read_line(&mut user).unwrap();
-            print!("Password: ");
-            std_out.flush().unwrap();
-            std_in.read_line(&mut passwd).unwrap();
-            Config {
-                user: user[..user.len() - 1].to_owned(),
-                password: passwd[..passwd.len() - 1].to_owned(),
+    let password = if !use_env {
+        Some(config.password.clone()).filter(|p| !p.is_empty())
+    } else if options.password_stdin {
+        match read_line(None) {
+            Some(password) => Some(password),
+            None => {
+                eprintln!("No password on stdin");
+                return None;
             }
+        }
+    } else {
+        env_value(PASSWORD_ENV).or_else(|| Some(config.password.clone()).filter(|p| !p.is_empty()))
+    };
+
+    if let Some(token) = env_value(TOKEN_ENV).filter(|_| use_env) {
+        let session = Session {
+            user: config.user.clone(),
+            token,
+            issued_at: 0,
         };
+        if let Err(err) = client.restore_session(session) {
+            Console::print_error(&err);
+            return None;
+        }
+        if let Some(ref password) = password {
+            client.set_credentials(&config.user, password);
+        }
+        return Some(client);
+    }
 
+    if config.user.is_empty() {
+        if options.no_interactive || options.password_stdin {
+            eprintln!("No user given, use --user or PICACG_USER");
+            return None;
+        }
+        match read_line(Some("User: ")).filter(|user| !user.is_empty()) {
+            Some(user) => config.user = user,
+            None => {
+                eprintln!("No user given");
+                return None;
+            }
+        }
+    }
 
+    let session: Option<Session> = configer.read(session_path).and_then(Result::ok);
+    match session.filter(|session| session.user == config.user) {
+        Some(session) => {
+            if let Err(err) = client.restore_session(session) {
+                Console::print_error(&err);
+                return None;
+            }
+            if let Some(ref password) = password {
+                client.set_credentials(&config.user, password);
+            }
+        }
+        None => {
+            let password = match password {
+                Some(password) => password,
+                None if options.no_interactive => {
+                    eprintln!(
+                        "No password for {}, use --password-stdin or {}",
+                        config.user, PASSWORD_ENV
+                    );
+                    return None;
+                }
+                None => match prompt_password(&config.user) {
+                    Some(password) => password,
+                    None => {
+                        eprintln!("Failed to read the password for {}", config.user);
+                        return None;
+                    }
+                },
+            };
+            if let Err(err) = client.login(&config.user, &password).await {
+                Console::print_error(&err);
+                return None;
+            }
+            write_session(configer, &client, session_path, use_env);
+        }
+    }
+    Some(client)
+}
 
-        if let Err(err) = client
-            .login(&config.user, &config.password)
-            .await
-        {
-            println!("{}", Console::format_error(&err));
+fn main() {
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let cli_options = GlobalOptions::parse();
+        let mut options = cli_options.clone();
+        let configer = Configer::new(&env::var("HOME").unwrap());
+
+        let mut stored: Config = match configer.read(CONFIG_PATH) {
+            Some(Ok(cfg)) => cfg,
+            Some(Err(err)) => {
+                eprintln!("Invalid config ~/{}: {:?}", CONFIG_PATH, err);
+                return;
+            }
+            None => Config::default(),
+        };
+        let profile = options.profile.clone();
+        let mut config = match stored.with_profile(profile.as_deref()) {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("{}", err);
+                return;
+            }
+        };
+        options.apply_config(&config);
+        let session_path = session_path(profile.as_deref());
+        let queue_path = queue_path(profile.as_deref());
+
+        match options.subcommand.clone() {
+            SubCommand::Blocklist(opts) => {
+                handle::blocklist::blocklist(&mut stored.blocklist, &options, opts);
+                configer.write(CONFIG_PATH, &stored);
+                return;
+            }
+            SubCommand::Config(opts) => {
+                handle::config::config(&configer, &mut stored, opts);
+                return;
+            }
+            SubCommand::User(UserOptions::Logout) => {
+                handle::user::logout(&configer, &mut stored);
+                return;
+            }
+            SubCommand::User(UserOptions::PunchIn { all_profiles: true }) => {
+                handle::user::punch_in_all(&configer, &stored, &cli_options).await;
+                return;
+            }
+            SubCommand::Queue(QueueOptions::List) => {
+                handle::queue::list(&options, &queue_path);
+                return;
+            }
+            SubCommand::Queue(QueueOptions::Clear { all }) => {
+                handle::queue::clear(all, &queue_path);
+                return;
+            }
+            _ => {}
+        }
+        if !options.subcommand.needs_account() {
             return;
         }
-        configer.write(".config/picacg/config", &config);
 
+        if let Some(user) = options.user.clone() {
+            if user != config.user {
+                config.password.clear();
+            }
+            config.user = user;
+        }
+        let prompted = config.user.is_empty();
+        let Some(client) = connect(&configer, &options, &mut config, &session_path, true).await
+        else {
+            std::process::exit(1);
+        };
+        // Remember a user name typed at the prompt for the account it belongs to.
+        let remember_user = prompted && !config.user.is_empty();
+        if remember_user {
+            match profile.as_deref() {
+                Some(name) => {
+                    if let Some(account) = stored.profiles.get_mut(name) {
+                        account.user = config.user.clone();
+                    }
+                }
+                None => stored.user = config.user.clone(),
+            }
+        }
+        // Rewriting the config drops a plaintext password left by older versions.
+        // Otherwise it is left as the user wrote it.
+        if remember_user || !stored.password.is_empty() {
+            configer.write(CONFIG_PATH, &stored);
+        }
+
+        // Only runs that may download keep the journal, so listings can run
+        // next to a download.
+        let queue = if options.download || options.subcommand.downloads() {
+            match DownloadQueue::with_journal(client.clone(), options.jobs(), home_path(&queue_path)) {
+                Ok(queue) => queue,
+                Err(err) => {
+                    eprintln!("Cannot open queue ~/{}: {}", queue_path, err);
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            DownloadQueue::new(client.clone(), options.jobs())
+        };
         match options.subcommand.clone() {
             SubCommand::Comic(opts) => match opts {
-                ComicOptions::Ranking => {
-                    handle::comic::ranking(&mut client, &options).await;
+                ComicOptions::Ranking { limit } => {
+                    handle::comic::ranking(&client, &options, &queue, limit).await;
                 }
-                ComicOptions::Metadata { cids, save_dir } => {
-                    handle::comic::metadata(&mut client, &options, cids, &save_dir).await;
+                ComicOptions::Metadata {
+                    cids,
+                    input,
+                    save_dir,
+                } => {
+                    let cids = match merge_ids(cids, input.as_deref(), parse_comic_id) {
+                        Ok(cids) => cids,
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            return;
+                        }
+                    };
+                    handle::comic::metadata(&client, &options, &queue, cids, &save_dir).await;
                 }
-                ComicOptions::Recommended { cids, save_dir } => {
-                    handle::comic::recommended(&mut client, &options, cids, &save_dir).await;
+                ComicOptions::Recommended {
+                    cids,
+                    limit,
+                    save_dir,
+                } => {
+                    handle::comic::recommended(&client, &options, &queue, cids, limit, &save_dir)
+                        .await;
                 }
                 ComicOptions::Eps {
                     cid,
-                    start,
-                    end,
+                    paging,
                     save_dir,
                 } => {
-                    handle::comic::eps(&mut client, &options, cid, start, end, &save_dir).await;
+                    handle::comic::eps(&client, &options, &queue, cid, paging, &save_dir).await;
                 }
                 ComicOptions::Pages {
                     cid,
                     start_index,
                     end_index,
-                    start,
-                    end,
+                    paging,
                     save_dir,
                 } => {
                     handle::comic::pages(
-                        &mut client,
+                        &client,
                         &options,
+                        &queue,
                         cid,
                         start_index,
                         end_index,
-                        start,
-                        end,
+                        paging,
                         &save_dir,
                     )
                     .await;
                 }
                 ComicOptions::PicLikeGet {
                     cid,
-                    start,
-                    end,
+                    paging,
                     save_dir,
                 } => {
-                    handle::comic::pic_like_get(&mut client, &options, cid, start, end, &save_dir)
+                    handle::comic::pic_like_get(&client, &options, &queue, cid, paging, &save_dir)
                         .await;
                 }
                 ComicOptions::Search {
                     keyword,
-                    start,
-                    end,
+                    sort,
+                    categories,
+                    paging,
                     save_dir,
                 } => {
-                    handle::comic::search(&mut client, &options, keyword, start, end, &save_dir).await;
+                    handle::comic::search(
+                        &client,
+                        &options,
+                        &queue,
+                        keyword,
+                        sort.or(config.search_sort).unwrap_or(SortOption::MaxLike),
+                        categories,
+                        paging,
+                        &save_dir,
+                    )
+                    .await;
                 }
 
                 ComicOptions::Favourites {
-                    start,
-                    end,
+                    sort,
+                    paging,
                     save_dir,
                 } => {
-                    handle::comic::favourites(&mut client, &options, start, end, &save_dir).await;
+                    handle::comic::favourites(
+                        &client,
+                        &options,
+                        &queue,
+                        sort.or(config.favourites_sort).unwrap_or(SortOption::DescByDate),
+                        paging,
+                        &save_dir,
+                    )
+                    .await;
                 }
-                ComicOptions::Download { cids, save_dir } => {
-                    handle::comic::download(&mut client, &options, cids, &save_dir).await;
+                ComicOptions::Download {
+                    cids,
+                    input,
+                    eps,
+                    first,
+                    latest,
+                    save_dir,
+                } => {
+                    let cids = match merge_ids(cids, input.as_deref(), parse_comic_id) {
+                        Ok(cids) => cids,
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            return;
+                        }
+                    };
+                    let selector = match (eps, first, latest) {
+                        (Some(eps), _, _) => eps,
+                        (_, Some(n), _) => EpSelector::First(n),
+                        (_, _, Some(n)) => EpSelector::Latest(n),
+                        _ => EpSelector::All,
+                    };
+                    handle::comic::download(&client, &options, &queue, cids, selector, &save_dir)
+                        .await;
                 }
             },
             SubCommand::Game(opts) => match opts {
                 GameOptions::Games {
-                    start,
-                    end,
+                    paging,
                     save_dir,
                 } => {
-                    handle::game::games(&mut client, &options, start, end, &save_dir).await;
+                    handle::game::games(&client, &options, &queue, paging, &save_dir).await;
                 }
                 GameOptions::Info { cids, save_dir, description } => {
-                    handle::game::info(&mut client, &options, cids, description, &save_dir).await;
+                    handle::game::info(&client, &options, &queue, cids, description, &save_dir).await;
                 }
-                GameOptions::Download { cids, save_dir } => {
-                    handle::game::download(&mut client, &options, cids, &save_dir).await;
+                GameOptions::Download {
+                    cids,
+                    input,
+                    save_dir,
+                } => {
+                    let cids = match merge_ids(cids, input.as_deref(), parse_game_id) {
+                        Ok(cids) => cids,
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            return;
+                        }
+                    };
+                    handle::game::download(&client, &options, &queue, cids, &save_dir).await;
                 }
             },
             SubCommand::User(opts) => match opts {
-                UserOptions::PunchIn => {
-                    handle::user::punch_in(&mut client).await;
+                UserOptions::PunchIn { .. } => {
+                    handle::user::punch_in(&client, &options).await;
                 }
                 UserOptions::Profile => {
-                    handle::user::profile(&mut client).await;
+                    handle::user::profile(&client, &options).await;
                 }
+                UserOptions::Logout => {}
+            },
+            SubCommand::Queue(opts) => match opts {
+                QueueOptions::Resume => handle::queue::resume(&queue),
+                QueueOptions::RetryFailed => handle::queue::retry_failed(&queue),
+                QueueOptions::List | QueueOptions::Clear { .. } => {}
             },
+            SubCommand::Blocklist(_) | SubCommand::Config(_) => {}
         }
+        queue.finish().await;
+        write_session(&configer, &client, &session_path, true);
     });
 }
@@ -0,0 +1,307 @@
+use std::io::{stdout, Write};
+
+use clap::ValueEnum;
+use libpicacg::responses::{
+    Comic, ComicMetadata, Ep, Game, GameInfo, Page, Profile, PunchIn, RecommendPicLike, SearchRow,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{blocklist::Blocklist, console::Console, queue::{Entry, Job}};
+
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+    Ndjson,
+    Csv,
+    Tsv,
+    Table,
+    Ids,
+}
+
+/// A libpicacg response that can be emitted by a listing command.
+pub trait Record: Serialize {
+    fn text(&self) -> String;
+
+    /// The comic or game id used by `--format ids`.
+    fn id(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Record for Comic {
+    fn text(&self) -> String {
+        Console::format_comic(self)
+    }
+
+    fn id(&self) -> Option<&str> {
+        Some(&self.id)
+    }
+}
+
+impl Record for ComicMetadata {
+    fn text(&self) -> String {
+        Console::format_comic_metadata(self)
+    }
+
+    fn id(&self) -> Option<&str> {
+        Some(&self.metadata.id)
+    }
+}
+
+impl Record for SearchRow {
+    fn text(&self) -> String {
+        Console::format_searchrow(self)
+    }
+
+    fn id(&self) -> Option<&str> {
+        Some(&self.id)
+    }
+}
+
+impl Record for Ep {
+    fn text(&self) -> String {
+        Console::format_ep(self)
+    }
+}
+
+impl Record for Page {
+    fn text(&self) -> String {
+        Console::format_page(self)
+    }
+}
+
+impl Record for RecommendPicLike {
+    fn text(&self) -> String {
+        Console::format_recommend_pic_like(self)
+    }
+
+    fn id(&self) -> Option<&str> {
+        Some(&self.id)
+    }
+}
+
+impl Record for Game {
+    fn text(&self) -> String {
+        Console::format_game(self)
+    }
+
+    fn id(&self) -> Option<&str> {
+        Some(&self.id)
+    }
+}
+
+impl Record for GameInfo {
+    fn text(&self) -> String {
+        Console::format_game_info(self)
+    }
+
+    fn id(&self) -> Option<&str> {
+        Some(&self.id)
+    }
+}
+
+impl Record for Profile {
+    fn text(&self) -> String {
+        Console::format_profile(self)
+    }
+}
+
+impl Record for PunchIn {
+    fn text(&self) -> String {
+        Console::format_punch_in(self)
+    }
+}
+
+impl Record for Blocklist {
+    fn text(&self) -> String {
+        Console::format_blocklist(self)
+    }
+}
+
+impl Record for Entry {
+    fn text(&self) -> String {
+        Console::format_job(self)
+    }
+
+    fn id(&self) -> Option<&str> {
+        match self.job {
+            Job::Comic { ref cid, .. }
+            | Job::Episode { ref cid, .. }
+            | Job::Pages { ref cid, .. }
+            | Job::Game { ref cid, .. } => Some(cid),
+        }
+    }
+}
+
+/// Writes records to stdout in the selected [`Format`].
+///
+/// `text`, `ids`, `ndjson`, `csv` and `tsv` are streamed as records arrive, `json` and
+/// `table` are buffered until [`Printer::finish`].
+pub struct Printer {
+    format: Format,
+    header: Option<Vec<String>>,
+    records: Vec<Value>,
+}
+
+impl Printer {
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            header: None,
+            records: Vec::new(),
+        }
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn print<T: Record>(&mut self, value: &T) {
+        match self.format {
+            Format::Text => {
+                println!("{}", value.text());
+            }
+            Format::Ndjson => {
+                println!("{}", serde_json::to_string(value).unwrap());
+            }
+            Format::Ids => {
+                if let Some(id) = value.id() {
+                    println!("{}", id);
+                }
+            }
+            Format::Json | Format::Table => {
+                self.records.push(serde_json::to_value(value).unwrap());
+            }
+            Format::Csv | Format::Tsv => {
+                let row = Self::flatten(value);
+                if self.header.is_none() {
+                    let header: Vec<String> = row.keys().cloned().collect();
+                    self.write_delimited(&header);
+                    self.header = Some(header);
+                }
+                let fields = self.fields(&row);
+                self.write_delimited(&fields);
+            }
+        }
+        stdout().flush().unwrap();
+    }
+
+    pub fn finish(&mut self) {
+        match self.format {
+            Format::Json => {
+                let records: Vec<Value> = self.records.drain(..).collect();
+                println!("{}", serde_json::to_string_pretty(&records).unwrap());
+            }
+            Format::Table => {
+                self.write_table();
+            }
+            _ => {}
+        }
+        stdout().flush().unwrap();
+    }
+
+    /// Nested objects become `parent.child` columns; arrays stay as JSON.
+    fn flatten<T: Serialize + ?Sized>(value: &T) -> Map<String, Value> {
+        fn walk(prefix: &str, value: Value, out: &mut Map<String, Value>) {
+            match value {
+                Value::Object(map) => {
+                    for (key, value) in map {
+                        let key = if prefix.is_empty() {
+                            key
+                        } else {
+                            format!("{}.{}", prefix, key)
+                        };
+                        walk(&key, value, out);
+                    }
+                }
+                value => {
+                    out.insert(prefix.to_string(), value);
+                }
+            }
+        }
+        let mut out = Map::new();
+        match serde_json::to_value(value).unwrap() {
+            Value::Object(map) => walk("", Value::Object(map), &mut out),
+            value => walk("value", value, &mut out),
+        }
+        out
+    }
+
+    fn cell(value: Option<&Value>) -> String {
+        match value {
+            None | Some(Value::Null) => String::new(),
+            Some(Value::String(s)) => s.clone(),
+            Some(value) => value.to_string(),
+        }
+    }
+
+    fn fields(&self, row: &Map<String, Value>) -> Vec<String> {
+        match self.header {
+            Some(ref header) => header.iter().map(|key| Self::cell(row.get(key))).collect(),
+            None => row.values().map(|value| Self::cell(Some(value))).collect(),
+        }
+    }
+
+    fn write_delimited(&self, fields: &[String]) {
+        let line = if self.format == Format::Tsv {
+            fields
+                .iter()
+                .map(|f| f.replace('\t', " ").replace(['\r', '\n'], " "))
+                .collect::<Vec<_>>()
+                .join("\t")
+        } else {
+            fields
+                .iter()
+                .map(|f| {
+                    if f.contains([',', '"', '\r', '\n']) {
+                        format!("\"{}\"", f.replace('"', "\"\""))
+                    } else {
+                        f.clone()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        println!("{}", line);
+    }
+
+    fn write_table(&mut self) {
+        let rows: Vec<Map<String, Value>> = self.records.drain(..).map(|v| Self::flatten(&v)).collect();
+        let Some(first) = rows.first() else {
+            return;
+        };
+        let header: Vec<String> = first.keys().cloned().collect();
+        self.header = Some(header.clone());
+        let cells: Vec<Vec<String>> = rows.iter().map(|row| self.fields(row)).collect();
+        let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+        for row in &cells {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let pad = |fields: &[String]| {
+            fields
+                .iter()
+                .zip(&widths)
+                .map(|(f, w)| format!("{}{}", f, " ".repeat(w - f.chars().count())))
+                .collect::<Vec<_>>()
+                .join(" | ")
+        };
+        println!("{}", pad(&header));
+        println!(
+            "{}",
+            widths
+                .iter()
+                .map(|w| "-".repeat(*w))
+                .collect::<Vec<_>>()
+                .join("-+-")
+        );
+        for row in &cells {
+            println!("{}", pad(row));
+        }
+    }
+}
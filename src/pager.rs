@@ -0,0 +1,322 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use clap::{ArgAction, Args};
+use futures::{future::BoxFuture, Stream};
+use libpicacg::{error::Error, Pagible};
+
+/// Pages of a paged listing to go through, counted from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageRange {
+    pub start: u64,
+    /// Last page to fetch, `None` runs up to the last page of the listing.
+    pub end: Option<u64>,
+}
+
+impl PageRange {
+    pub fn new(start: u64, end: Option<u64>) -> Self {
+        Self {
+            start: start.max(1),
+            end,
+        }
+    }
+
+    pub fn all() -> Self {
+        Self::new(1, None)
+    }
+
+    fn is_last(&self, page: u64) -> bool {
+        self.end.is_some_and(|end| page >= end)
+    }
+}
+
+/// Parses `A..B`, `A..`, `..B` or a single page `A`, `A..=B` is read as `A..B`.
+pub fn parse_pages(value: &str) -> Result<PageRange, String> {
+    let invalid = || format!("`{}` is not a page range such as 2..5, 3.. or ..4", value);
+    let page = |page: &str| page.trim().parse::<u64>().ok().filter(|page| *page > 0);
+    let Some((start, end)) = value.split_once("..") else {
+        let page = page(value).ok_or_else(invalid)?;
+        return Ok(PageRange::new(page, Some(page)));
+    };
+    let end = end.strip_prefix('=').unwrap_or(end);
+    let start = if start.trim().is_empty() { 1 } else { page(start).ok_or_else(invalid)? };
+    let end = if end.trim().is_empty() { None } else { Some(page(end).ok_or_else(invalid)?) };
+    if end.is_some_and(|end| end < start) {
+        return Err(format!("page range `{}` ends before it starts", value));
+    }
+    Ok(PageRange::new(start, end))
+}
+
+// Which part of a listing to go through, shared by every listing subcommand.
+// Not a doc comment, clap would show it as the about text of the subcommands.
+#[derive(Args, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Paging {
+    /// First page [default: 1]
+    #[clap(short = 's', long = "start", conflicts_with = "pages")]
+    pub start: Option<u64>,
+    /// Last page [default: the start page]
+    #[clap(short = 'u', long = "until", conflicts_with_all = ["pages", "all"])]
+    pub until: Option<u64>,
+    /// Pages to fetch as A..B, A.., ..B or a single page
+    #[clap(long = "pages", value_parser = parse_pages, conflicts_with = "all")]
+    pub pages: Option<PageRange>,
+    /// Fetches every page from the start page on
+    #[clap(long = "all", default_value = "false", action = ArgAction::SetTrue)]
+    pub all: bool,
+    /// Stops after this many items, fetching further pages as needed
+    #[clap(long = "limit")]
+    pub limit: Option<usize>,
+}
+
+impl Paging {
+    /// The pages to fetch, `until_end` being the global `-e/--end`.
+    ///
+    /// Without `--until`, `--pages`, `--all` or `--limit` only the start page
+    /// is fetched, unless `until_end` asks for every page.
+    pub fn range(&self, until_end: bool) -> PageRange {
+        if let Some(pages) = self.pages {
+            return pages;
+        }
+        let start = self.start.unwrap_or(1);
+        if self.until.is_some() {
+            PageRange::new(start, self.until)
+        } else if self.all || until_end || self.limit.is_some() {
+            PageRange::new(start, None)
+        } else {
+            PageRange::new(start, Some(start))
+        }
+    }
+}
+
+type Fetch<'a, P> = Box<dyn FnMut(u64) -> BoxFuture<'a, Result<P, Error>> + Send + 'a>;
+
+/// The pages of a paged API call as a stream. The request for the next page
+/// goes out as soon as the previous one arrived, and makes progress whenever
+/// the stream is polled. The stream ends after the first error.
+pub struct Pages<'a, P> {
+    fetch: Fetch<'a, P>,
+    range: PageRange,
+    next: Option<BoxFuture<'a, Result<P, Error>>>,
+    ready: Option<Result<P, Error>>,
+    /// Page to request once the caller agrees to go on.
+    queued: Option<u64>,
+}
+
+/// Streams the pages in `range`, `fetch` requests a page by its number.
+pub fn pages<'a, P, F>(range: PageRange, mut fetch: F) -> Pages<'a, P>
+where
+    P: Pagible,
+    F: FnMut(u64) -> BoxFuture<'a, Result<P, Error>> + Send + 'a,
+{
+    let next = Some(fetch(range.start));
+    Pages {
+        fetch: Box::new(fetch),
+        range,
+        next,
+        ready: None,
+        queued: None,
+    }
+}
+
+impl<'a, P: Pagible + Unpin> Pages<'a, P> {
+    /// Drives the pending request without handing out its page.
+    fn prefetch(&mut self, cx: &mut Context<'_>) {
+        if self.ready.is_some() {
+            return;
+        }
+        if let Some(next) = self.next.as_mut() {
+            if let Poll::Ready(page) = next.as_mut().poll(cx) {
+                self.next = None;
+                self.ready = Some(page);
+            }
+        }
+    }
+
+    /// Hands out the next page without requesting the one after it, see
+    /// [`Pages::fetch_queued`].
+    fn poll_page(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<P, Error>>> {
+        self.prefetch(cx);
+        match self.ready.take() {
+            Some(Ok(page)) => {
+                if page.has_next() && !self.range.is_last(page.current()) {
+                    self.queued = Some(page.next());
+                }
+                Poll::Ready(Some(Ok(page)))
+            }
+            Some(Err(err)) => Poll::Ready(Some(Err(err))),
+            None if self.next.is_some() => Poll::Pending,
+            None => Poll::Ready(None),
+        }
+    }
+
+    /// Requests the page after the last one handed out, if there is one.
+    fn fetch_queued(&mut self, cx: &mut Context<'_>) {
+        if let Some(page) = self.queued.take() {
+            self.next = Some((self.fetch)(page));
+            self.prefetch(cx);
+        }
+    }
+
+    /// Ends the stream after the pages already handed out.
+    fn stop(&mut self) {
+        self.queued = None;
+        self.next = None;
+        self.ready = None;
+    }
+}
+
+impl<'a, P: Pagible + Unpin> Stream for Pages<'a, P> {
+    type Item = Result<P, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let page = this.poll_page(cx);
+        this.fetch_queued(cx);
+        page
+    }
+}
+
+type Split<'a, P, T> = Box<dyn Fn(&P) -> Vec<T> + Send + 'a>;
+
+/// The items on the pages of a paged API call, see [`Pages`].
+pub struct Items<'a, P, T> {
+    pages: Pages<'a, P>,
+    split: Split<'a, P, T>,
+    buffer: VecDeque<T>,
+    /// Items still to hand out, unlimited when `None`.
+    remaining: Option<usize>,
+}
+
+/// Streams the items on the pages in `range`, `split` takes the items out of a page.
+pub fn items<'a, P, T, F, S>(range: PageRange, fetch: F, split: S) -> Items<'a, P, T>
+where
+    P: Pagible,
+    F: FnMut(u64) -> BoxFuture<'a, Result<P, Error>> + Send + 'a,
+    S: Fn(&P) -> Vec<T> + Send + 'a,
+{
+    Items {
+        pages: pages(range, fetch),
+        split: Box::new(split),
+        buffer: VecDeque::new(),
+        remaining: None,
+    }
+}
+
+impl<'a, P, T> Items<'a, P, T> {
+    /// Ends the stream after `limit` items, without requesting pages beyond them.
+    pub fn limit(mut self, limit: Option<usize>) -> Self {
+        self.remaining = limit;
+        self
+    }
+}
+
+impl<'a, P: Pagible + Unpin, T: Unpin> Stream for Items<'a, P, T> {
+    type Item = Result<T, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(item) = this.buffer.pop_front() {
+                this.pages.prefetch(cx);
+                return Poll::Ready(Some(Ok(item)));
+            }
+            if this.remaining == Some(0) {
+                this.pages.stop();
+                return Poll::Ready(None);
+            }
+            match this.pages.poll_page(cx) {
+                Poll::Ready(Some(Ok(page))) => {
+                    let mut items = (this.split)(&page);
+                    if let Some(remaining) = this.remaining.as_mut() {
+                        items.truncate(*remaining);
+                        *remaining -= items.len();
+                    }
+                    if this.remaining == Some(0) {
+                        this.pages.stop();
+                    } else {
+                        this.pages.fetch_queued(cx);
+                    }
+                    this.buffer.extend(items);
+                }
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Command {
+        #[clap(flatten)]
+        paging: Paging,
+    }
+
+    fn paging(args: &[&str]) -> Result<Paging, clap::Error> {
+        Command::try_parse_from(["picacg"].iter().chain(args)).map(|command| command.paging)
+    }
+
+    #[test]
+    fn parses_page_ranges() {
+        let range = |start, end| PageRange::new(start, end);
+        assert_eq!(parse_pages("2..5"), Ok(range(2, Some(5))));
+        assert_eq!(parse_pages("2..=5"), Ok(range(2, Some(5))));
+        assert_eq!(parse_pages("3.."), Ok(range(3, None)));
+        assert_eq!(parse_pages("..4"), Ok(range(1, Some(4))));
+        assert_eq!(parse_pages(" 7 "), Ok(range(7, Some(7))));
+        assert_eq!(parse_pages(".."), Ok(PageRange::all()));
+    }
+
+    #[test]
+    fn rejects_bad_page_ranges() {
+        for value in ["", "0", "x", "1..x", "0..2", "1...3"] {
+            assert_eq!(
+                parse_pages(value),
+                Err(format!("`{}` is not a page range such as 2..5, 3.. or ..4", value))
+            );
+        }
+        assert_eq!(
+            parse_pages("5..3"),
+            Err("page range `5..3` ends before it starts".to_string())
+        );
+    }
+
+    #[test]
+    fn range_defaults_to_the_start_page() {
+        assert_eq!(paging(&[]).unwrap().range(false), PageRange::new(1, Some(1)));
+        assert_eq!(paging(&["-s", "3"]).unwrap().range(false), PageRange::new(3, Some(3)));
+        assert_eq!(paging(&["-s", "3"]).unwrap().range(true), PageRange::new(3, None));
+    }
+
+    #[test]
+    fn range_follows_the_options() {
+        let range = |args: &[&str]| paging(args).unwrap().range(false);
+        assert_eq!(range(&["-s", "2", "-u", "4"]), PageRange::new(2, Some(4)));
+        assert_eq!(range(&["-u", "4"]), PageRange::new(1, Some(4)));
+        assert_eq!(range(&["--pages", "3..6"]), PageRange::new(3, Some(6)));
+        assert_eq!(range(&["-s", "2", "--all"]), PageRange::new(2, None));
+        assert_eq!(range(&["--limit", "50"]), PageRange::new(1, None));
+        assert_eq!(range(&["-u", "2", "--limit", "50"]), PageRange::new(1, Some(2)));
+        assert_eq!(
+            paging(&["--pages", "3.."]).unwrap().range(true),
+            PageRange::new(3, None)
+        );
+    }
+
+    #[test]
+    fn conflicting_options_are_rejected() {
+        assert!(paging(&["--pages", "1..2", "-s", "2"]).is_err());
+        assert!(paging(&["--pages", "1..2", "-u", "2"]).is_err());
+        assert!(paging(&["--pages", "1..2", "--all"]).is_err());
+        assert!(paging(&["--all", "-u", "2"]).is_err());
+    }
+}
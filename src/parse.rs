@@ -0,0 +1,149 @@
+/// Query keys that carry an id in Picacg share links and web URLs.
+const ID_KEYS: [&str; 5] = ["cid", "comicId", "gid", "gameId", "id"];
+
+pub fn is_id(value: &str) -> bool {
+    value.len() == 24 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn is_game_url(value: &str) -> bool {
+    let lower = value.to_ascii_lowercase();
+    lower.contains("/game") || lower.contains("gid=") || lower.contains("gameid=")
+}
+
+/// The host is left out, `picacomic.com` serves games too.
+fn is_comic_url(value: &str) -> bool {
+    let lower = value.to_ascii_lowercase();
+    lower.starts_with("pica://comic")
+        || lower.contains("/comics/")
+        || lower.contains("/pcomicview")
+        || lower.contains("cid=")
+        || lower.contains("comicid=")
+}
+
+/// Pulls a 24-hex id out of a bare id, a share link, a web URL or an image URL.
+///
+/// Query keys such as `cid=` win over ids found in the path, so links that embed
+/// several ids still resolve to the one the link points at.
+fn extract_id(value: &str) -> Option<String> {
+    let value = value.trim();
+    if is_id(value) {
+        return Some(value.to_ascii_lowercase());
+    }
+    let without_fragment = value.split('#').next().unwrap_or(value);
+    if let Some((_, query)) = without_fragment.split_once('?') {
+        for key in ID_KEYS {
+            for pair in query.split('&') {
+                if let Some((k, v)) = pair.split_once('=') {
+                    if k == key && is_id(v) {
+                        return Some(v.to_ascii_lowercase());
+                    }
+                }
+            }
+        }
+    }
+    value
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .find(|segment| is_id(segment))
+        .map(|id| id.to_ascii_lowercase())
+}
+
+pub fn parse_comic_id(value: &str) -> Result<String, String> {
+    if !is_id(value.trim()) && is_game_url(value) {
+        return Err(format!("`{}` is a game link, not a comic", value));
+    }
+    extract_id(value).ok_or_else(|| {
+        format!(
+            "`{}` is not a comic id (24 hex characters) or a Picacg comic link",
+            value
+        )
+    })
+}
+
+pub fn parse_game_id(value: &str) -> Result<String, String> {
+    if !is_id(value.trim()) && is_comic_url(value) {
+        return Err(format!("`{}` is a comic link, not a game", value));
+    }
+    extract_id(value).ok_or_else(|| {
+        format!(
+            "`{}` is not a game id (24 hex characters) or a Picacg game link",
+            value
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "5821859b5f6b9a4f93dbf6e9";
+
+    #[test]
+    fn accepts_bare_ids_in_any_case() {
+        assert_eq!(parse_comic_id(ID).unwrap(), ID);
+        assert_eq!(parse_comic_id(&format!(" {} ", ID.to_uppercase())).unwrap(), ID);
+        assert_eq!(parse_game_id(ID).unwrap(), ID);
+    }
+
+    #[test]
+    fn finds_comic_ids_in_links() {
+        for link in [
+            format!("https://manhuabika.com/pcomicview/?cid={}", ID),
+            format!("https://manhuabika.com/pcomicview/?cid={}#top", ID),
+            format!("https://picaapi.picacomic.com/comics/{}/eps?page=1", ID),
+            format!("pica://comic?comicId={}", ID),
+            format!("https://storage1.picacomic.com/static/{}/cover.jpg", ID),
+        ] {
+            assert_eq!(parse_comic_id(&link).unwrap(), ID, "{}", link);
+        }
+    }
+
+    #[test]
+    fn query_keys_win_over_path_ids() {
+        let other = "0123456789abcdef01234567";
+        let link = format!("https://manhuabika.com/comics/{}/view?cid={}", other, ID);
+        assert_eq!(parse_comic_id(&link).unwrap(), ID);
+        let link = format!("https://manhuabika.com/comics/{}/view?page=2&id={}", other, ID);
+        assert_eq!(parse_comic_id(&link).unwrap(), ID);
+    }
+
+    #[test]
+    fn finds_game_ids_in_links() {
+        for link in [
+            format!("https://manhuabika.com/pgamedetail/?gid={}", ID),
+            format!("https://picaapi.picacomic.com/games/{}", ID),
+            format!("pica://game?gameId={}", ID),
+        ] {
+            assert_eq!(parse_game_id(&link).unwrap(), ID, "{}", link);
+        }
+    }
+
+    #[test]
+    fn rejects_game_links_as_comics() {
+        let link = format!("https://manhuabika.com/pgamedetail/?gid={}", ID);
+        assert_eq!(
+            parse_comic_id(&link).unwrap_err(),
+            format!("`{}` is a game link, not a comic", link)
+        );
+    }
+
+    #[test]
+    fn rejects_comic_links_as_games() {
+        for link in [
+            format!("https://manhuabika.com/pcomicview/?cid={}", ID),
+            format!("https://picaapi.picacomic.com/comics/{}/eps?page=1", ID),
+            format!("pica://comic?comicId={}", ID),
+        ] {
+            assert_eq!(
+                parse_game_id(&link).unwrap_err(),
+                format!("`{}` is a comic link, not a game", link)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_values_without_an_id() {
+        assert!(parse_comic_id("5821859b5f6b9a4f93dbf6e").is_err());
+        assert!(parse_comic_id("https://manhuabika.com/pcomicview/?cid=xyz").is_err());
+        assert!(parse_game_id("").is_err());
+    }
+}
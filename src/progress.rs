@@ -0,0 +1,144 @@
+use std::{
+    io::{stderr, Write},
+    sync::Mutex,
+};
+
+use size_utils::Size;
+
+use crate::{
+    console::Console,
+    queue::{Job, JobStatus},
+};
+
+/// Progress of a running download, reported to a [`ProgressSink`].
+#[derive(Clone, Copy)]
+pub enum Progress<'a> {
+    /// Images of one API page of an episode.
+    Episode {
+        name: &'a str,
+        page: u64,
+        pages: u64,
+        /// Images of the page done so far, counted from 1.
+        image: u64,
+        images: u64,
+        /// Images of the episode done so far, counted from 1.
+        downloaded: u64,
+        total: u64,
+    },
+    Game {
+        completed: Size,
+        length: Size,
+        path: &'a str,
+    },
+    /// The download reported under `name`, the episode name or game path, is over.
+    Finished { name: &'a str },
+    /// A job of a [`crate::queue::DownloadQueue`] changed its status, ids
+    /// count from 1.
+    Job {
+        id: usize,
+        job: &'a Job,
+        status: &'a JobStatus,
+    },
+}
+
+/// Receives the progress of image and game downloads.
+pub trait ProgressSink: Send + Sync {
+    fn report(&self, progress: Progress<'_>);
+}
+
+/// Progress lines on stderr, one per running download, kept below whatever
+/// else is printed.
+struct Lines {
+    /// Name and text of each running download.
+    lines: Vec<(String, String)>,
+    /// Terminal rows taken by the lines drawn last.
+    drawn: usize,
+}
+
+static LINES: Mutex<Lines> = Mutex::new(Lines {
+    lines: Vec::new(),
+    drawn: 0,
+});
+
+impl Lines {
+    fn erase(&mut self, out: &mut impl Write) {
+        if self.drawn > 1 {
+            write!(out, "{}[{}A", 27 as char, self.drawn - 1).ok();
+        }
+        write!(out, "\r{}[J", 27 as char).ok();
+        self.drawn = 0;
+    }
+
+    fn draw(&mut self, out: &mut impl Write) {
+        self.erase(out);
+        for (index, (_, text)) in self.lines.iter().enumerate() {
+            let separator = if index == 0 { "" } else { "\n" };
+            write!(out, "{}{}", separator, text).ok();
+        }
+        self.drawn = self.lines.len();
+        out.flush().ok();
+    }
+
+    fn set(&mut self, name: &str, text: String) {
+        match self.lines.iter_mut().find(|(line, _)| line == name) {
+            Some((_, line)) => *line = text,
+            None => self.lines.push((name.to_string(), text)),
+        }
+    }
+
+    fn remove(&mut self, name: &str) {
+        self.lines.retain(|(line, _)| line != name);
+    }
+}
+
+/// Takes the progress lines off the terminal until the next report, so a
+/// message can be printed in their place.
+pub fn clear() {
+    LINES.lock().unwrap().erase(&mut stderr());
+}
+
+/// Keeps one line per running download at the bottom of stderr, the default
+/// sink. Stdout is left to the listed records.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsoleProgress;
+
+impl ProgressSink for ConsoleProgress {
+    fn report(&self, progress: Progress<'_>) {
+        let mut lines = LINES.lock().unwrap();
+        let mut out = stderr().lock();
+        match progress {
+            Progress::Episode {
+                name,
+                page,
+                pages,
+                image,
+                images,
+                downloaded,
+                total,
+            } => lines.set(
+                name,
+                Console::format_download_ep(name, page, pages, image, images, downloaded, total),
+            ),
+            Progress::Game {
+                completed,
+                length,
+                path,
+            } => lines.set(path, Console::format_download_game(completed, length, path)),
+            Progress::Finished { name } => lines.remove(name),
+            Progress::Job { status, .. } if *status == JobStatus::Pending => return,
+            Progress::Job { id, job, status } => {
+                lines.erase(&mut out);
+                writeln!(out, "Job {} ({}): {}", id, job, status).ok();
+            }
+        }
+        lines.draw(&mut out);
+    }
+}
+
+/// Drops every report, for callers with their own output.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {
+    fn report(&self, _progress: Progress<'_>) {}
+}
@@ -0,0 +1,571 @@
+use std::{
+    collections::HashSet,
+    env,
+    ffi::OsString,
+    fmt, fs,
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Mutex},
+};
+
+use libpicacg::error::Error;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{mpsc, watch, Mutex as AsyncMutex},
+    task::{self, JoinHandle},
+};
+
+use crate::{client::Client, console::Console, progress::Progress, retry, selector::EpSelector};
+
+/// Something to download, saved under `save_dir`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Job {
+    /// The episodes of a comic picked by `eps`.
+    Comic {
+        cid: String,
+        eps: EpSelector,
+        save_dir: String,
+    },
+    /// One episode of a comic by its order.
+    Episode {
+        cid: String,
+        order: u64,
+        save_dir: String,
+    },
+    /// The images listed on one API page of an episode.
+    Pages {
+        cid: String,
+        order: u64,
+        page: u64,
+        save_dir: String,
+    },
+    Game { cid: String, save_dir: String },
+}
+
+impl fmt::Display for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Job::Comic { cid, .. } => write!(f, "comic {}", cid),
+            Job::Episode { cid, order, .. } => write!(f, "episode {} of comic {}", order, cid),
+            Job::Pages { cid, order, page, .. } => {
+                write!(f, "page {} of episode {} of comic {}", page, order, cid)
+            }
+            Job::Game { cid, .. } => write!(f, "game {}", cid),
+        }
+    }
+}
+
+impl Job {
+    pub fn save_dir(&self) -> &str {
+        match self {
+            Job::Comic { save_dir, .. }
+            | Job::Episode { save_dir, .. }
+            | Job::Pages { save_dir, .. }
+            | Job::Game { save_dir, .. } => save_dir,
+        }
+    }
+
+    /// Resolves a relative save dir against the working directory, so a
+    /// journaled job saves to the same place when resumed from elsewhere.
+    fn absolute(mut self) -> Self {
+        let (Job::Comic { save_dir, .. }
+        | Job::Episode { save_dir, .. }
+        | Job::Pages { save_dir, .. }
+        | Job::Game { save_dir, .. }) = &mut self;
+        if Path::new(save_dir.as_str()).is_relative() {
+            if let Ok(cwd) = env::current_dir() {
+                *save_dir = cwd.join(save_dir.as_str()).to_string_lossy().into_owned();
+            }
+        }
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Pending,
+    Active,
+    Done,
+    /// Failed with the given error once retries were used up.
+    Failed(String),
+}
+
+impl fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobStatus::Pending => write!(f, "pending"),
+            JobStatus::Active => write!(f, "active"),
+            JobStatus::Done => write!(f, "done"),
+            JobStatus::Failed(err) => write!(f, "failed: {}", err),
+        }
+    }
+}
+
+/// A job of a queue with its status.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Position in the queue counted from 1, renumbered when jobs are removed.
+    pub id: usize,
+    #[serde(flatten)]
+    pub job: Job,
+    pub status: JobStatus,
+}
+
+/// Reads the jobs kept at `path`, none when the file does not exist.
+///
+/// Jobs left active by a process that stopped are pending again.
+pub fn read_journal(path: &Path) -> io::Result<Vec<Entry>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut entries: Vec<Entry> = serde_json::from_slice(&data)
+        .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+    for (index, entry) in entries.iter_mut().enumerate() {
+        entry.id = index + 1;
+        if entry.status == JobStatus::Active {
+            entry.status = JobStatus::Pending;
+        }
+    }
+    Ok(entries)
+}
+
+/// Removes done and failed jobs, and pending ones as well with `all`, then
+/// renumbers the rest. Returns how many were removed.
+pub fn remove_finished(entries: &mut Vec<Entry>, all: bool) -> usize {
+    let total = entries.len();
+    entries.retain(|entry| !all && entry.status == JobStatus::Pending);
+    for (index, entry) in entries.iter_mut().enumerate() {
+        entry.id = index + 1;
+    }
+    total - entries.len()
+}
+
+/// `path` with `suffix` added to its file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Replaces the jobs kept at `path`, through a temporary file so a crash
+/// never leaves half a journal behind.
+pub fn write_journal(path: &Path, entries: &[Entry]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp = with_suffix(path, ".tmp");
+    fs::write(&temp, serde_json::to_vec_pretty(entries).unwrap())?;
+    fs::rename(&temp, path)
+}
+
+/// Keeps other processes from changing the journal at the path it was taken
+/// for, released when dropped.
+#[derive(Debug)]
+pub struct JournalLock {
+    path: PathBuf,
+}
+
+impl JournalLock {
+    /// Takes the lock of the journal at `path` through a `.lock` file next to
+    /// it holding this process id. A lock left by a process that no longer
+    /// runs is taken over where that can be told, on Linux.
+    pub fn acquire(path: &Path) -> io::Result<Self> {
+        let lock = with_suffix(path, ".lock");
+        if let Some(parent) = lock.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        loop {
+            match fs::OpenOptions::new().write(true).create_new(true).open(&lock) {
+                Ok(mut file) => {
+                    write!(file, "{}", process::id())?;
+                    return Ok(Self { path: lock });
+                }
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    let owner = fs::read_to_string(&lock).unwrap_or_default();
+                    let owner = owner.trim();
+                    let proc = Path::new("/proc");
+                    if owner.parse::<u32>().is_err() || !proc.is_dir() || proc.join(owner).exists() {
+                        return Err(io::Error::new(
+                            ErrorKind::WouldBlock,
+                            format!(
+                                "in use by another run (pid {}), remove {} if it is not running",
+                                if owner.is_empty() { "unknown" } else { owner },
+                                lock.display()
+                            ),
+                        ));
+                    }
+                    fs::remove_file(&lock)?;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for JournalLock {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Saves every snapshot sent to `receiver` at `path` off the async workers,
+/// in order. Snapshots sent while one is written are folded into the next
+/// write, so only the latest is saved.
+async fn save_journal(path: PathBuf, mut receiver: watch::Receiver<Vec<Entry>>) {
+    while receiver.changed().await.is_ok() {
+        let entries = receiver.borrow_and_update().clone();
+        let target = path.clone();
+        if let Err(err) = task::spawn_blocking(move || write_journal(&target, &entries)).await.unwrap() {
+            Console::clear_line();
+            eprintln!("Failed to save the queue to {}: {}", path.display(), err);
+        }
+    }
+}
+
+struct State {
+    entries: Vec<Entry>,
+    /// Jobs handed to the workers by this queue, so each runs once per process.
+    queued: HashSet<usize>,
+}
+
+struct Shared {
+    client: Client,
+    state: Mutex<State>,
+    /// Where snapshots of the entries go to be saved.
+    journal: Option<watch::Sender<Vec<Entry>>>,
+}
+
+impl Shared {
+    /// Marks job `id` as queued and pending, `false` when it already was queued.
+    fn queue(&self, id: usize) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !state.queued.insert(id) {
+            return false;
+        }
+        state.entries[id - 1].status = JobStatus::Pending;
+        drop(state);
+        self.changed(id);
+        true
+    }
+
+    fn set_status(&self, id: usize, status: JobStatus) {
+        self.state.lock().unwrap().entries[id - 1].status = status;
+        self.changed(id);
+    }
+
+    /// Hands the journal to be saved and reports the status of job `id`.
+    fn changed(&self, id: usize) {
+        let state = self.state.lock().unwrap();
+        if let Some(ref journal) = self.journal {
+            journal.send_replace(state.entries.clone());
+        }
+        let entry = state.entries[id - 1].clone();
+        drop(state);
+        self.client.progress().report(Progress::Job {
+            id,
+            job: &entry.job,
+            status: &entry.status,
+        });
+    }
+
+    async fn run(&self, id: usize) {
+        let job = self.state.lock().unwrap().entries[id - 1].job.clone();
+        self.set_status(id, JobStatus::Active);
+        let status = match self.download(&job).await {
+            Ok(()) => JobStatus::Done,
+            Err(err) => JobStatus::Failed(Console::format_error(&err)),
+        };
+        self.set_status(id, status);
+    }
+
+    async fn download(&self, job: &Job) -> Result<(), Error> {
+        let client = &self.client;
+        match job {
+            Job::Comic { cid, eps, save_dir } => {
+                retry!(client, comic_download_selected_eps(cid, eps, save_dir))
+            }
+            Job::Episode {
+                cid,
+                order,
+                save_dir,
+            } => retry!(client, comic_download_ep(cid, *order, save_dir)),
+            Job::Pages {
+                cid,
+                order,
+                page,
+                save_dir,
+            } => retry!(client, comic_download_pages(cid, *order, *page, Some(*page), save_dir)),
+            Job::Game { cid, save_dir } => retry!(client, game_download(cid, save_dir)),
+        }
+    }
+}
+
+/// Downloads jobs with up to `parallel` running at once. The images of all
+/// jobs share the client's concurrency limit, jobs can be added while others
+/// run and every status change goes to the client's progress sink.
+///
+/// With a journal every change is saved to disk, so jobs cut short by a crash
+/// can be picked up again with [`DownloadQueue::resume`].
+pub struct DownloadQueue {
+    shared: Arc<Shared>,
+    sender: mpsc::UnboundedSender<usize>,
+    workers: Vec<JoinHandle<()>>,
+    saver: Option<JoinHandle<()>>,
+    /// Held for as long as the queue may write its journal.
+    lock: Option<JournalLock>,
+}
+
+impl DownloadQueue {
+    /// Starts the workers, so it has to be called inside a tokio runtime.
+    pub fn new(client: Client, parallel: usize) -> Self {
+        Self::start(client, parallel, Vec::new(), None)
+    }
+
+    /// A queue kept in the journal at `path`. Jobs already in it are known but
+    /// only run again when pushed, resumed or retried. The journal stays
+    /// locked against other processes until the queue is finished or dropped.
+    pub fn with_journal(client: Client, parallel: usize, path: PathBuf) -> io::Result<Self> {
+        let lock = JournalLock::acquire(&path)?;
+        let entries = read_journal(&path)?;
+        Ok(Self::start(client, parallel, entries, Some((path, lock))))
+    }
+
+    fn start(
+        client: Client,
+        parallel: usize,
+        entries: Vec<Entry>,
+        journal: Option<(PathBuf, JournalLock)>,
+    ) -> Self {
+        let (journal, saver, lock) = match journal {
+            Some((path, lock)) => {
+                let (sender, receiver) = watch::channel(entries.clone());
+                (Some(sender), Some(tokio::spawn(save_journal(path, receiver))), Some(lock))
+            }
+            None => (None, None, None),
+        };
+        let shared = Arc::new(Shared {
+            client,
+            state: Mutex::new(State {
+                entries,
+                queued: HashSet::new(),
+            }),
+            journal,
+        });
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let receiver = Arc::new(AsyncMutex::new(receiver));
+        let workers = (0..parallel.max(1))
+            .map(|_| {
+                let shared = shared.clone();
+                let receiver = receiver.clone();
+                tokio::spawn(async move {
+                    loop {
+                        let Some(id) = receiver.lock().await.recv().await else {
+                            break;
+                        };
+                        shared.run(id).await;
+                    }
+                })
+            })
+            .collect();
+        Self {
+            shared,
+            sender,
+            workers,
+            saver,
+            lock,
+        }
+    }
+
+    fn enqueue(&self, id: usize) -> bool {
+        if !self.shared.queue(id) {
+            return false;
+        }
+        self.sender.send(id).unwrap();
+        true
+    }
+
+    /// Adds a job and returns its id. A job already in the queue is run again
+    /// under its old id, unless this queue ran it already.
+    pub fn push(&self, job: Job) -> usize {
+        let job = match self.lock {
+            Some(_) => job.absolute(),
+            None => job,
+        };
+        let id = {
+            let mut state = self.shared.state.lock().unwrap();
+            match state.entries.iter().position(|entry| entry.job == job) {
+                Some(index) => index + 1,
+                None => {
+                    let id = state.entries.len() + 1;
+                    state.entries.push(Entry {
+                        id,
+                        job,
+                        status: JobStatus::Pending,
+                    });
+                    id
+                }
+            }
+        };
+        self.enqueue(id);
+        id
+    }
+
+    /// Runs the pending jobs, returns how many were started.
+    pub fn resume(&self) -> usize {
+        self.enqueue_where(|status| *status == JobStatus::Pending)
+    }
+
+    /// Runs the failed jobs again, returns how many were started.
+    pub fn retry_failed(&self) -> usize {
+        self.enqueue_where(|status| matches!(status, JobStatus::Failed(_)))
+    }
+
+    fn enqueue_where(&self, pick: impl Fn(&JobStatus) -> bool) -> usize {
+        let ids: Vec<usize> = self
+            .shared
+            .state
+            .lock()
+            .unwrap()
+            .entries
+            .iter()
+            .filter(|entry| pick(&entry.status))
+            .map(|entry| entry.id)
+            .collect();
+        ids.into_iter().filter(|id| self.enqueue(*id)).count()
+    }
+
+    pub fn status(&self, id: usize) -> Option<JobStatus> {
+        let state = self.shared.state.lock().unwrap();
+        id.checked_sub(1)
+            .and_then(|index| state.entries.get(index))
+            .map(|entry| entry.status.clone())
+    }
+
+    /// Every job in the queue with its status.
+    pub fn jobs(&self) -> Vec<Entry> {
+        self.shared.state.lock().unwrap().entries.clone()
+    }
+
+    /// Waits for every job queued so far and for the journal to be saved, and
+    /// returns all jobs with their status.
+    pub async fn finish(self) -> Vec<Entry> {
+        drop(self.sender);
+        for worker in self.workers {
+            worker.await.unwrap();
+        }
+        let entries = self.shared.state.lock().unwrap().entries.clone();
+        // The workers are gone, so this drops the journal sender and ends the saver.
+        drop(self.shared);
+        if let Some(saver) = self.saver {
+            saver.await.unwrap();
+        }
+        drop(self.lock);
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A path in the temp dir unique to this process and test.
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("picacg-queue-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    fn game(cid: &str, save_dir: &str) -> Job {
+        Job::Game {
+            cid: cid.to_string(),
+            save_dir: save_dir.to_string(),
+        }
+    }
+
+    fn entry(id: usize, job: Job, status: JobStatus) -> Entry {
+        Entry { id, job, status }
+    }
+
+    #[test]
+    fn reads_active_jobs_back_as_pending() {
+        let path = temp_path("read");
+        let entries = vec![
+            entry(7, game("a", "/a"), JobStatus::Active),
+            entry(7, game("b", "/b"), JobStatus::Done),
+            entry(7, game("c", "/c"), JobStatus::Failed("gone".to_string())),
+        ];
+        write_journal(&path, &entries).unwrap();
+        assert_eq!(
+            read_journal(&path).unwrap(),
+            vec![
+                entry(1, game("a", "/a"), JobStatus::Pending),
+                entry(2, game("b", "/b"), JobStatus::Done),
+                entry(3, game("c", "/c"), JobStatus::Failed("gone".to_string())),
+            ]
+        );
+        fs::remove_file(&path).unwrap();
+        assert_eq!(read_journal(&path).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn clearing_keeps_pending_jobs_unless_all() {
+        let entries = vec![
+            entry(1, game("a", "/a"), JobStatus::Done),
+            entry(2, game("b", "/b"), JobStatus::Pending),
+            entry(3, game("c", "/c"), JobStatus::Failed("gone".to_string())),
+            entry(4, game("d", "/d"), JobStatus::Pending),
+        ];
+        let mut cleared = entries.clone();
+        assert_eq!(remove_finished(&mut cleared, false), 2);
+        assert_eq!(
+            cleared,
+            vec![
+                entry(1, game("b", "/b"), JobStatus::Pending),
+                entry(2, game("d", "/d"), JobStatus::Pending),
+            ]
+        );
+        let mut cleared = entries;
+        assert_eq!(remove_finished(&mut cleared, true), 4);
+        assert!(cleared.is_empty());
+    }
+
+    // A current-thread runtime never runs the workers while the test does not
+    // await, so nothing is downloaded.
+    #[tokio::test]
+    async fn push_finds_journaled_jobs_by_their_absolute_save_dir() {
+        let path = temp_path("push");
+        let cwd = env::current_dir().unwrap();
+        let absolute = cwd.join("out").to_string_lossy().into_owned();
+        write_journal(&path, &[entry(1, game("a", &absolute), JobStatus::Done)]).unwrap();
+        let queue = DownloadQueue::with_journal(Client::new(), 1, path.clone()).unwrap();
+        assert_eq!(queue.push(game("a", "out")), 1);
+        assert_eq!(queue.push(game("a", "out")), 1);
+        assert_eq!(queue.push(game("b", "out")), 2);
+        assert_eq!(queue.status(1), Some(JobStatus::Pending));
+        assert_eq!(
+            queue.jobs().iter().map(|entry| entry.job.clone()).collect::<Vec<_>>(),
+            vec![game("a", &absolute), game("b", &absolute)]
+        );
+        drop(queue);
+        fs::remove_file(&path).ok();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn lock_is_taken_over_from_a_process_that_is_gone() {
+        let path = temp_path("lock");
+        let lock_path = with_suffix(&path, ".lock");
+        fs::write(&lock_path, u32::MAX.to_string()).unwrap();
+        let lock = JournalLock::acquire(&path).unwrap();
+        assert_eq!(fs::read_to_string(&lock_path).unwrap(), process::id().to_string());
+        let err = JournalLock::acquire(&path).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        drop(lock);
+        assert!(!lock_path.exists());
+    }
+}
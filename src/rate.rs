@@ -0,0 +1,73 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use tokio::{
+    sync::Mutex,
+    time::{sleep_until, Instant},
+};
+
+/// Spaces out calls to at most `per_second` a second, each one delayed by up
+/// to `jitter` more so the calls do not arrive on a fixed beat.
+pub struct RateLimiter {
+    interval: Duration,
+    jitter: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// `jitter` defaults to a quarter of the interval.
+    pub fn new(per_second: f64, jitter: Option<Duration>) -> Self {
+        let interval = Duration::from_secs_f64(1.0 / per_second);
+        Self {
+            interval,
+            jitter: jitter.unwrap_or(interval / 4),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn random_jitter(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return Duration::ZERO;
+        }
+        let random = RandomState::new().build_hasher().finish();
+        self.jitter.mul_f64(random as f64 / u64::MAX as f64)
+    }
+
+    /// Waits until the next call is allowed.
+    pub async fn wait(&self) {
+        let mut next = self.next.lock().await;
+        if *next > Instant::now() {
+            sleep_until(*next).await;
+        }
+        *next = Instant::now() + self.interval + self.random_jitter();
+    }
+}
+
+/// Caps the bytes received by all downloads together at `per_second` a second.
+pub struct Bandwidth {
+    per_second: f64,
+    /// When the bytes taken so far have been paid for.
+    until: std::sync::Mutex<Instant>,
+}
+
+impl Bandwidth {
+    pub fn new(per_second: u64) -> Self {
+        Self {
+            per_second: per_second.max(1) as f64,
+            until: std::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits as long as `bytes` take at the cap, after the bytes taken before.
+    pub async fn take(&self, bytes: usize) {
+        let until = {
+            let mut until = self.until.lock().unwrap();
+            *until = (*until).max(Instant::now()) + Duration::from_secs_f64(bytes as f64 / self.per_second);
+            *until
+        };
+        sleep_until(until).await;
+    }
+}
@@ -0,0 +1,121 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Picks episodes of a comic by their `Ep.order`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EpSelector {
+    #[default]
+    All,
+    /// Inclusive ranges, `None` as end means up to the last episode.
+    Ranges(Vec<(u64, Option<u64>)>),
+    First(u64),
+    Latest(u64),
+}
+
+impl EpSelector {
+    /// Returns the orders out of `orders` that this selector picks, in ascending order.
+    pub fn select(&self, orders: &[u64]) -> Vec<u64> {
+        let mut orders = orders.to_vec();
+        orders.sort_unstable();
+        orders.dedup();
+        match self {
+            EpSelector::All => orders,
+            EpSelector::Ranges(ranges) => orders
+                .into_iter()
+                .filter(|order| {
+                    ranges
+                        .iter()
+                        .any(|(start, end)| order >= start && end.is_none_or(|end| *order <= end))
+                })
+                .collect(),
+            EpSelector::First(n) => orders.into_iter().take(*n as usize).collect(),
+            EpSelector::Latest(n) => {
+                let skip = orders.len().saturating_sub(*n as usize);
+                orders.into_iter().skip(skip).collect()
+            }
+        }
+    }
+}
+
+impl FromStr for EpSelector {
+    type Err = String;
+
+    /// Parses lists such as `1-5,8,12-`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<u64>()
+                .map_err(|_| format!("`{}` is not an episode number", v.trim()))
+        };
+        let mut ranges = Vec::new();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let range = match part.split_once('-') {
+                Some((start, end)) => {
+                    let start = if start.trim().is_empty() { 1 } else { parse(start)? };
+                    let end = if end.trim().is_empty() {
+                        None
+                    } else {
+                        Some(parse(end)?)
+                    };
+                    if end.is_some_and(|end| end < start) {
+                        return Err(format!("episode range `{}` ends before it starts", part));
+                    }
+                    (start, end)
+                }
+                None => {
+                    let order = parse(part)?;
+                    (order, Some(order))
+                }
+            };
+            ranges.push(range);
+        }
+        if ranges.is_empty() {
+            return Err("empty episode selection".to_string());
+        }
+        Ok(EpSelector::Ranges(ranges))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> EpSelector {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_orders_and_ranges() {
+        assert_eq!(
+            parse("1-5, 8,12-"),
+            EpSelector::Ranges(vec![(1, Some(5)), (8, Some(8)), (12, None)])
+        );
+        assert_eq!(parse("-3"), EpSelector::Ranges(vec![(1, Some(3))]));
+        assert_eq!(parse("4,,"), EpSelector::Ranges(vec![(4, Some(4))]));
+        assert_eq!(parse("2-2"), EpSelector::Ranges(vec![(2, Some(2))]));
+    }
+
+    #[test]
+    fn rejects_bad_selections() {
+        let err = |s: &str| s.parse::<EpSelector>().unwrap_err();
+        assert_eq!(err(""), "empty episode selection");
+        assert_eq!(err(" , "), "empty episode selection");
+        assert_eq!(err("a"), "`a` is not an episode number");
+        assert_eq!(err("1-x"), "`x` is not an episode number");
+        assert_eq!(err("5-3"), "episode range `5-3` ends before it starts");
+    }
+
+    #[test]
+    fn selects_sorted_unique_orders() {
+        let orders = [5, 1, 3, 2, 4, 3];
+        assert_eq!(EpSelector::All.select(&orders), [1, 2, 3, 4, 5]);
+        assert_eq!(parse("4-,1").select(&orders), [1, 4, 5]);
+        assert_eq!(parse("2-3,3-4").select(&orders), [2, 3, 4]);
+        assert_eq!(parse("7-").select(&orders), [] as [u64; 0]);
+        assert_eq!(EpSelector::First(2).select(&orders), [1, 2]);
+        assert_eq!(EpSelector::Latest(2).select(&orders), [4, 5]);
+        assert_eq!(EpSelector::Latest(9).select(&orders), [1, 2, 3, 4, 5]);
+    }
+}